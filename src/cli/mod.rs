@@ -1,18 +1,53 @@
+mod overlay;
+
 use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::Duration;
 
 use regex::Regex;
+use term::Colour as TermColor;
 
 use level::level::Level;
 use level::component::Component;
+use level::distance::Distances;
+use level::goal::Target;
+use level::dead::DeadSquares;
+use level::order::GoalOrder;
+use defs::pos::{Pos, NULL_POS};
+use search::allocation::Tasks;
+use search::anytime::{anytime, WEIGHTS};
+use search::matching::Matching;
+use search::patterns::{PatternDb, SHAPES};
+use search::cbs::{cbs, plan_cost, Objective};
+use search::graph::Solution;
+use search::hda::hdastar;
+use search::help::cooperative;
+use search::parking::Parking;
+use search::independence::independence;
+use search::portfolio::{portfolio, Policy, DEFAULT};
+use search::prioritized::prioritized_or_cbs;
+use search::problem::{LevelProblem, Step};
+use search::optimize::optimize;
+use search::schedule::parallelize;
+use search::stats::{Limits, SearchStats};
+use search::strategy::Strategy;
+use state::joint::JointAction;
+use state::simulator::Simulator;
+use state::state::State;
+use self::overlay::Overlay;
+
+// expansions allowed to each local search when building the pattern database
+const PATTERN_LIMIT: u64 = 100_000;
 
 pub struct Cli {
-    level: Option<Level>,
-    comps: Option<Vec<Component>>
+    level: Option<Arc<Level>>,
+    comps: Option<Vec<Component>>,
+    patterns: Option<Arc<PatternDb>>
 }
 
 impl Cli {
     pub fn run<I>(input_cmds: I) where I: Iterator<Item=String> {
-        let mut cli = Cli { level: None, comps: None };
+        let mut cli = Cli { level: None, comps: None, patterns: None };
         let mut cmds = VecDeque::<String>::new();
         let mut rl = ::rustyline::Editor::<()>::new();
         let re_split = Regex::new(r"\s+").unwrap();
@@ -40,7 +75,7 @@ impl Cli {
                         if let Some(level_path) = cmds.pop_front() {
                             if let Ok(lvl) = Level::from_file(&level_path) {
                                 cli.comps = Some(Component::all(&lvl));
-                                cli.level = Some(lvl);
+                                cli.level = Some(Arc::new(lvl));
                             } else {
                                 println!("Could not read level.");
                             }
@@ -76,6 +111,203 @@ impl Cli {
                             Err(msg) => println!("{}", msg)
                         }
                     }
+                    "solve" | "s" => {
+                        let strategy = cmds.pop_front().and_then(|s| Strategy::parse(&s));
+                        let timeout = cmds.front().and_then(|s| s.parse::<u64>().ok());
+                        if timeout.is_some() { cmds.pop_front(); }
+
+                        match (&cli.level, strategy) {
+                            (Some(lvl), Some(strategy)) => cli.solve(lvl, strategy, timeout),
+                            (&None, _) => println!("No level loaded."),
+                            (_, None)  => println!("Invalid strategy.")
+                        }
+                    }
+                    "portfolio" | "p" => {
+                        let timeout = cmds.front().and_then(|s| s.parse::<u64>().ok());
+                        if timeout.is_some() { cmds.pop_front(); }
+                        let policy = match cmds.front().map(|s| s.as_str()) {
+                            Some("first") => Some(Policy::First),
+                            Some("best")  => Some(Policy::Best),
+                            _ => None
+                        };
+                        if policy.is_some() { cmds.pop_front(); }
+
+                        if let Some(ref lvl) = cli.level {
+                            cli.run_portfolio(lvl, policy.unwrap_or(Policy::First), timeout);
+                        } else {
+                            println!("No level loaded.");
+                        }
+                    }
+                    "parallel_astar" | "pa" => {
+                        let threads = cmds.pop_front().and_then(|s| s.parse::<usize>().ok());
+                        let timeout = cmds.front().and_then(|s| s.parse::<u64>().ok());
+                        if timeout.is_some() { cmds.pop_front(); }
+
+                        match (&cli.level, threads) {
+                            (Some(lvl), Some(threads)) => cli.run_hdastar(lvl, threads, timeout),
+                            (&None, _) => println!("No level loaded."),
+                            (_, None)  => println!("Invalid number of threads.")
+                        }
+                    }
+                    "cbs" => {
+                        let objective = match cmds.front().map(|s| s.as_str()) {
+                            Some("soc")      => Some(Objective::SumOfCosts),
+                            Some("makespan") => Some(Objective::Makespan),
+                            _ => None
+                        };
+                        if objective.is_some() { cmds.pop_front(); }
+                        let timeout = cmds.front().and_then(|s| s.parse::<u64>().ok());
+                        if timeout.is_some() { cmds.pop_front(); }
+
+                        if let Some(ref lvl) = cli.level {
+                            cli.run_cbs(lvl, objective.unwrap_or(Objective::SumOfCosts), false, timeout);
+                        } else {
+                            println!("No level loaded.");
+                        }
+                    }
+                    "prioritized" | "pp" => {
+                        let timeout = cmds.front().and_then(|s| s.parse::<u64>().ok());
+                        if timeout.is_some() { cmds.pop_front(); }
+
+                        if let Some(ref lvl) = cli.level {
+                            cli.run_cbs(lvl, Objective::SumOfCosts, true, timeout);
+                        } else {
+                            println!("No level loaded.");
+                        }
+                    }
+                    "independence" | "id" => {
+                        let timeout = cmds.front().and_then(|s| s.parse::<u64>().ok());
+                        if timeout.is_some() { cmds.pop_front(); }
+
+                        if let Some(ref lvl) = cli.level {
+                            cli.run_independence(lvl, timeout);
+                        } else {
+                            println!("No level loaded.");
+                        }
+                    }
+                    "cooperative" | "co" => {
+                        let timeout = cmds.front().and_then(|s| s.parse::<u64>().ok());
+                        if timeout.is_some() { cmds.pop_front(); }
+
+                        if let Some(ref lvl) = cli.level {
+                            cli.run_cooperative(lvl, timeout);
+                        } else {
+                            println!("No level loaded.");
+                        }
+                    }
+                    "allocate" | "a" => {
+                        let auction = match cmds.front().map(|s| s.as_str()) {
+                            Some("hungarian") => Some(false),
+                            Some("auction")   => Some(true),
+                            _ => None
+                        };
+                        if auction.is_some() { cmds.pop_front(); }
+
+                        if let Some(ref lvl) = cli.level {
+                            print_tasks(lvl, auction.unwrap_or(false));
+                        } else {
+                            println!("No level loaded.");
+                        }
+                    }
+                    "dead_squares" | "ds" => {
+                        if let Some(ref lvl) = cli.level {
+                            let dead = DeadSquares::new(lvl);
+                            for (letter, color) in dead.kinds() {
+                                let mut overlay = Overlay::new(lvl);
+                                let cells = dead.cells(letter, color).unwrap();
+                                let (rows, cols) = cells.size();
+                                for row in 0..rows {
+                                    for col in 0..cols {
+                                        if cells[(row, col)] { overlay.mark(Pos::new(row as i8, col as i8), 'x', TermColor::Red); }
+                                    }
+                                }
+                                println!("{} ({:?}):\n{}", (b'A' + letter) as char, color, overlay);
+                            }
+                        } else {
+                            println!("No level loaded.");
+                        }
+                    }
+                    "goal_order" | "go" => {
+                        if let Some(ref lvl) = cli.level {
+                            let order = GoalOrder::new(lvl);
+                            let state = State::new(lvl);
+                            let filled = lvl.goals().iter().map(|g| state.satisfies(g)).collect::<Vec<bool>>();
+                            let ready = order.ready(&filled);
+                            let mut overlay = Overlay::new(lvl);
+                            for (g, goal) in lvl.goals().iter().enumerate() {
+                                let rank = ::std::char::from_digit(order.rank(g) as u32, 36).unwrap_or('*');
+                                let color = if ready.contains(&g) { TermColor::Green } else { TermColor::Yellow };
+                                overlay.mark(goal.pos, rank, color);
+                            }
+                            println!("Goals by rank, lowest first, those ready to fill in green:\n{}", overlay);
+                            for (g, goal) in lvl.goals().iter().enumerate() {
+                                if order.before(g).is_empty() { continue; }
+                                let cells = order.before(g).iter().map(|&h| lvl.goals()[h].pos.to_string()).collect::<Vec<String>>();
+                                println!("  {} after {}", goal.pos, cells.join(" "));
+                            }
+                        } else {
+                            println!("No level loaded.");
+                        }
+                    }
+                    "parking" | "pk" => {
+                        let from = match (cmds.front().and_then(|s| s.parse::<i8>().ok()), cmds.get(1).and_then(|s| s.parse::<i8>().ok())) {
+                            (Some(row), Some(col)) => { cmds.pop_front(); cmds.pop_front(); Some(Pos::new(row, col)) }
+                            _ => None
+                        };
+
+                        if let Some(ref lvl) = cli.level {
+                            let state = State::new(lvl);
+                            let from = from.unwrap_or_else(|| state.agents().first().cloned().unwrap_or(NULL_POS));
+                            if from == NULL_POS {
+                                println!("No agent 0 in the level, give a position.");
+                            } else if state.in_bounds(from) {
+                                let parking = Parking::new(lvl);
+                                let spots = parking.spots(lvl, &state, from, &[]);
+                                let mut overlay = Overlay::new(lvl);
+                                let (rows, cols) = lvl.size();
+                                for row in 0..rows {
+                                    for col in 0..cols {
+                                        let pos = Pos::new(row as i8, col as i8);
+                                        if state.is_free(pos) && parking.is_excluded(pos) { overlay.mark(pos, 'x', TermColor::Red); }
+                                    }
+                                }
+                                for (rank, &(pos, _)) in spots.iter().enumerate() {
+                                    let label = ::std::char::from_digit(rank as u32, 36).unwrap_or('*');
+                                    overlay.mark(pos, label, if rank < 10 { TermColor::Green } else { TermColor::Yellow });
+                                }
+                                overlay.mark(from, 'o', TermColor::Cyan);
+                                println!("Parking spots for {}, closest first, x never parked on:\n{}", from, overlay);
+                            } else {
+                                println!("Position outside of the level.");
+                            }
+                        } else {
+                            println!("No level loaded.");
+                        }
+                    }
+                    "build_patterns" => {
+                        if let Some(path) = cmds.pop_front() {
+                            let db = PatternDb::build(&SHAPES, PATTERN_LIMIT);
+                            match db.save(&path) {
+                                Ok(()) => println!("{} patterns written to {}.", db.len(), path),
+                                Err(msg) => println!("{}", msg)
+                            }
+                        } else {
+                            println!("No path provided.");
+                        }
+                    }
+                    "load_patterns" => {
+                        if let Some(path) = cmds.pop_front() {
+                            match PatternDb::load(&path) {
+                                Ok(db) => {
+                                    println!("{} patterns loaded.", db.len());
+                                    cli.patterns = Some(Arc::new(db));
+                                }
+                                Err(msg) => println!("{}", msg)
+                            }
+                        } else {
+                            println!("No path provided.");
+                        }
+                    }
                     "exit" | "quit" => { break; }
                     "help" => {
                         println!("Available commands:");
@@ -84,6 +316,19 @@ impl Cli {
                         println!(" - print_level");
                         println!(" - exit / quit");
                         println!(" - regions <component_number> <nb_regions>");
+                        println!(" - portfolio [timeout_secs] [first|best]");
+                        println!(" - parallel_astar <threads> [timeout_secs]");
+                        println!(" - cbs [soc|makespan] [timeout_secs]");
+                        println!(" - prioritized [timeout_secs]");
+                        println!(" - independence [timeout_secs]");
+                        println!(" - cooperative [timeout_secs]");
+                        println!(" - allocate [hungarian|auction]");
+                        println!(" - dead_squares");
+                        println!(" - goal_order");
+                        println!(" - parking [row col]");
+                        println!(" - build_patterns <path>");
+                        println!(" - load_patterns <path>");
+                        println!(" - solve bfs|dfs|astar|wastar:<weight>|greedy|idastar|anytime|reverse|bidir [timeout_secs]");
                         println!(" - help");
                     }
                     _ => { println!("Unknown command '{}'", cmd); }
@@ -92,6 +337,111 @@ impl Cli {
         }
     }
 
+    fn solve(&self, level: &Level, strategy: Strategy, timeout: Option<u64>) {
+        let mut problem = LevelProblem::new(level);
+        if let Some(ref db) = self.patterns { problem.set_patterns(db); }
+        let mut limits = Limits::new().progress(Duration::from_secs(1), report_progress);
+        if let Some(secs) = timeout { limits = limits.timeout(Duration::from_secs(secs)); }
+
+        let outcome = if strategy == Strategy::Anytime {
+            let h = Matching::new(level);
+            anytime(&problem, &h, &WEIGHTS, &limits, |s, w| println!("  plan of {} steps (w={})", s.cost, w))
+        } else {
+            strategy.run(&problem, &limits)
+        };
+
+        if let Some(ref solution) = outcome.solution {
+            print_plan(level, solution);
+            println!("Solved in {} steps.", solution.cost);
+        } else {
+            println!("No solution.");
+        }
+        println!("{}", outcome.stats);
+    }
+
+    fn run_portfolio(&self, level: &Arc<Level>, policy: Policy, timeout: Option<u64>) {
+        let mut limits = Limits::new();
+        if let Some(secs) = timeout { limits = limits.timeout(Duration::from_secs(secs)); }
+
+        let result = portfolio(level.clone(), self.patterns.clone(), &DEFAULT, policy, &limits);
+        for &(strategy, ref stats) in &result.runs {
+            println!("  {:?}: {}", strategy, stats);
+        }
+
+        if let Some((strategy, solution)) = result.best {
+            print_plan(level, &solution);
+            println!("Solved in {} steps by {:?}.", solution.cost, strategy);
+        } else {
+            println!("No solution.");
+        }
+    }
+
+    fn run_hdastar(&self, level: &Arc<Level>, threads: usize, timeout: Option<u64>) {
+        let mut limits = Limits::new().progress(Duration::from_secs(1), report_progress);
+        if let Some(secs) = timeout { limits = limits.timeout(Duration::from_secs(secs)); }
+
+        let outcome = hdastar(level.clone(), self.patterns.clone(), threads, &limits);
+        if let Some(ref solution) = outcome.solution {
+            print_plan(level, solution);
+            println!("Solved in {} steps.", solution.cost);
+        } else {
+            println!("No solution.");
+        }
+        println!("{}", outcome.stats);
+    }
+
+    fn run_cbs(&self, level: &Level, objective: Objective, prioritized_first: bool, timeout: Option<u64>) {
+        let mut limits = Limits::new().progress(Duration::from_secs(1), report_progress);
+        if let Some(secs) = timeout { limits = limits.timeout(Duration::from_secs(secs)); }
+
+        let outcome = if prioritized_first { prioritized_or_cbs(level, objective, &limits) } else { cbs(level, objective, &limits) };
+        if let Some(ref solution) = outcome.solution {
+            let plan = print_joint_plan(level, &solution.plan);
+            println!("Cost {} once optimized, {} as found.", plan_cost(&plan, objective), solution.cost);
+        } else {
+            println!("No solution.");
+        }
+        println!("{}", outcome.stats);
+    }
+
+    fn run_independence(&self, level: &Level, timeout: Option<u64>) {
+        let mut limits = Limits::new().progress(Duration::from_secs(1), report_progress);
+        if let Some(secs) = timeout { limits = limits.timeout(Duration::from_secs(secs)); }
+
+        let result = independence(level, &limits);
+        for group in &result.groups {
+            println!("  group {:?}: agents {:?}", group.colors, group.agents);
+        }
+
+        let outcome = result.outcome;
+        if let Some(ref solution) = outcome.solution {
+            print_joint_plan(level, &solution.plan);
+        } else {
+            println!("No solution.");
+        }
+        println!("{}", outcome.stats);
+    }
+
+    fn run_cooperative(&self, level: &Level, timeout: Option<u64>) {
+        let mut limits = Limits::new().progress(Duration::from_secs(1), report_progress);
+        if let Some(secs) = timeout { limits = limits.timeout(Duration::from_secs(secs)); }
+
+        let result = cooperative(level, &limits);
+        for request in &result.requests {
+            let cells = request.cells.iter().map(|c| c.to_string()).collect::<Vec<String>>();
+            println!("  agent {} asks agent {} to clear {}", request.requester, request.helper, cells.join(" "));
+        }
+
+        let outcome = result.outcome;
+        if let Some(ref solution) = outcome.solution {
+            print_plan(level, solution);
+            println!("Solved in {} steps.", solution.cost);
+        } else {
+            println!("No solution.");
+        }
+        println!("{}", outcome.stats);
+    }
+
     fn get_component(&self, opt_comp_nb: Option<String>) -> Result<&Component, &'static str> {
         if let Some(nb) = opt_comp_nb.and_then(|s| s.parse::<usize>().ok()) {
             if self.comps.as_ref().is_none() {
@@ -106,3 +456,50 @@ impl Cli {
         }
     }
 }
+
+// the plan as the server gets it, steps of different agents run together when they can
+fn print_plan(level: &Level, solution: &Solution<Step>) {
+    let joint = optimize(level, &parallelize(&State::new(level), &solution.plan));
+    for step in &joint { println!("{}", step); }
+    println!("{} joint actions for {} steps.", joint.len(), solution.plan.len());
+}
+
+// the optimized plan, replayed on the server simulator to report on it
+fn print_joint_plan(level: &Level, plan: &[JointAction]) -> Vec<JointAction> {
+    let optimized = optimize(level, plan);
+    let mut server = Simulator::new(level);
+    for joint in &optimized {
+        println!("{}", joint);
+        if let Err(e) = server.step(&joint.to_string()) { println!("Server error: {}", e); }
+    }
+    println!("{}", server.report());
+    optimized
+}
+
+fn print_tasks(level: &Level, auction: bool) {
+    let start = State::new(level);
+    let tables = Distances::all(level);
+    let tasks = if auction { Tasks::auction(level, &start, &tables) } else { Tasks::hungarian(level, &start, &tables) };
+
+    for (agent, list) in tasks.agents.iter().enumerate() {
+        if list.is_empty() { continue; }
+        println!("Agent {}:", agent);
+        for task in list {
+            let goal = level.goals()[task.goal];
+            match (goal.target, task.box_pos) {
+                (Target::Box(letter), Some(pos)) => {
+                    println!("  {} {} -> {}, cost {}", (b'A' + letter) as char, pos, goal.pos, task.cost)
+                }
+                _ => println!("  agent -> {}, cost {}", goal.pos, task.cost)
+            }
+        }
+    }
+    for &g in &tasks.unassigned {
+        println!("Unassigned goal at {}", level.goals()[g].pos);
+    }
+    println!("Total cost {}, largest load {}.", tasks.cost(), tasks.makespan());
+}
+
+fn report_progress(stats: &SearchStats) {
+    println!("  {}", stats);
+}
@@ -0,0 +1,42 @@
+use std::fmt;
+
+use term::Colour as Color;
+
+use defs::grid::Grid;
+use defs::pos::Pos;
+use level::level::Level;
+
+/// A level with some cells highlighted, to display the result of an analysis.
+pub struct Overlay<'a> {
+    level: &'a Level,
+    marks: Grid<Option<(char, Color)>>
+}
+
+impl<'a> Overlay<'a> {
+    pub fn new(level: &'a Level) -> Overlay<'a> {
+        let (rows, cols) = level.size();
+        Overlay { level: level, marks: Grid::new(rows, cols) }
+    }
+
+    /// Shows `label` on a `color` background instead of the item on `pos`.
+    pub fn mark(&mut self, pos: Pos, label: char, color: Color) {
+        self.marks[pos] = Some((label, color));
+    }
+}
+
+impl<'a> fmt::Display for Overlay<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (rows, cols) = self.level.size();
+        for row in 0..rows {
+            for col in 0..cols {
+                let pos = Pos::new(row as i8, col as i8);
+                match self.marks[pos] {
+                    Some((label, color)) => write!(f, "{}", color.reverse().paint(label.to_string()))?,
+                    None => write!(f, "{}", self.level[pos])?
+                }
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
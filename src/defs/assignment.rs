@@ -0,0 +1,98 @@
+use na::core::DMatrix;
+
+/// Minimum cost assignment (Hungarian algorithm, O(n²m)).
+///
+/// Assigns every row to a distinct column if there are no more rows than columns, every
+/// column to a distinct row otherwise. Returns the column of each row (None if unassigned)
+/// and the total cost.
+pub fn min_cost_assignment(cost: &DMatrix<u32>) -> (Vec<Option<usize>>, u64) {
+    let (rows, cols) = cost.shape();
+
+    if rows > cols {
+        let (col_to_row, total) = min_cost_assignment(&cost.transpose());
+        let mut row_to_col = vec!(None; rows);
+        for (col, row) in col_to_row.iter().enumerate() {
+            if let Some(r) = *row { row_to_col[r] = Some(col); }
+        }
+        return (row_to_col, total);
+    }
+
+    // potentials and matching are 1-based, index 0 being a virtual column
+    let (n, m) = (rows, cols);
+    let mut u = vec!(0i64; n + 1);
+    let mut v = vec!(0i64; m + 1);
+    let mut p = vec!(0usize; m + 1);       // row matched to each column
+    let mut way = vec!(0usize; m + 1);
+
+    for i in 1..(n + 1) {
+        p[0] = i;
+        let mut j0 = 0;
+        let mut minv = vec!(i64::MAX; m + 1);
+        let mut used = vec!(false; m + 1);
+
+        loop {
+            used[j0] = true;
+            let i0 = p[j0];
+            let mut delta = i64::MAX;
+            let mut j1 = 0;
+
+            for j in 1..(m + 1) {
+                if used[j] { continue; }
+                let c = cost[(i0 - 1, j - 1)] as i64 - u[i0] - v[j];
+                if c < minv[j] { minv[j] = c; way[j] = j0; }
+                if minv[j] < delta { delta = minv[j]; j1 = j; }
+            }
+
+            for j in 0..(m + 1) {
+                if used[j] { u[p[j]] += delta; v[j] -= delta; }
+                else { minv[j] -= delta; }
+            }
+
+            j0 = j1;
+            if p[j0] == 0 { break; }
+        }
+
+        loop {
+            let j1 = way[j0];
+            p[j0] = p[j1];
+            j0 = j1;
+            if j0 == 0 { break; }
+        }
+    }
+
+    let mut row_to_col = vec!(None; n);
+    let mut total = 0;
+    for j in 1..(m + 1) {
+        if p[j] != 0 {
+            row_to_col[p[j] - 1] = Some(j - 1);
+            total += cost[(p[j] - 1, j - 1)] as u64;
+        }
+    }
+
+    (row_to_col, total)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn square() {
+        let cost = DMatrix::from_row_slice(3, 3, &[4, 1, 3,
+                                                    2, 0, 5,
+                                                    3, 2, 2]);
+        let (assignment, total) = min_cost_assignment(&cost);
+        assert_eq!(total, 5);
+        assert_eq!(assignment, vec!(Some(1), Some(0), Some(2)));
+    }
+
+    #[test]
+    fn rectangular() {
+        let cost = DMatrix::from_row_slice(3, 2, &[1, 9,
+                                                    2, 8,
+                                                    9, 1]);
+        let (assignment, total) = min_cost_assignment(&cost);
+        assert_eq!(total, 2);
+        assert_eq!(assignment, vec!(Some(0), None, Some(1)));
+    }
+}
@@ -19,3 +19,25 @@ pub fn name(dir: Dir) -> &'static str {
         _     => "?Dir?"
     }
 }
+
+/// Single letter used by the server protocol, e.g. `Move(N)`.
+pub fn letter(dir: Dir) -> &'static str {
+    match dir {
+        NORTH => "N",
+        EAST  => "E",
+        SOUTH => "S",
+        WEST  => "W",
+        _     => "?"
+    }
+}
+
+/// Direction of a protocol letter, the inverse of `letter`.
+pub fn from_letter(letter: &str) -> Option<Dir> {
+    match letter {
+        "N" => Some(NORTH),
+        "E" => Some(EAST),
+        "S" => Some(SOUTH),
+        "W" => Some(WEST),
+        _   => None
+    }
+}
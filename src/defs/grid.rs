@@ -3,7 +3,7 @@ use std::ops::{Index, IndexMut};
 use std::slice::{Iter, IterMut};
 use super::pos::Pos;
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Eq, Hash)]
 pub struct Grid<T> {
     rows: usize,
     data: Vec<T>
@@ -1,3 +1,4 @@
 pub mod dir;
 pub mod pos;
 pub mod grid;
+pub mod assignment;
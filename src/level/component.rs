@@ -89,6 +89,13 @@ impl Component {
         self.pos_to_index[(pos.row as usize, pos.col as usize)]
     }
 
+    /// Whether `pos` is a cell of the component; safe for positions outside of the grid.
+    pub fn contains(&self, pos: Pos) -> bool {
+        let (rows, cols) = self.size();
+        pos.row >= 0 && pos.col >= 0 && (pos.row as usize) < rows && (pos.col as usize) < cols
+            && self.index_of(pos) >= 0
+    }
+
     pub fn pos_of(&self, index: i16) -> Pos {
         self.index_to_pos[index as usize]
     }
@@ -0,0 +1,148 @@
+use std::collections::{HashMap, VecDeque};
+
+use defs::dir::DIRS;
+use defs::grid::Grid;
+use defs::pos::Pos;
+use super::component::Component;
+use super::goal::Target;
+use super::item::{Item, Color};
+use super::level::Level;
+
+/// Static dead squares: for each box letter and color, the cells from which such a box can
+/// never reach a goal of its letter, whatever the other boxes and agents do. Only the cells
+/// of components holding goals are considered.
+///
+/// Computed by pulling boxes backwards from the goals over each component. A box can move
+/// from `y` to a neighbour `x` if `y` has another free neighbour to push from, or `x` another
+/// free neighbour to pull towards, sideways ones included. Boxes with no agent of their color
+/// cannot move at all, so only their goals are live. Box kinds without goals, or with more
+/// boxes than goals, are never dead since a box of theirs may stay anywhere.
+pub struct DeadSquares {
+    dead: HashMap<(u8, Color), Grid<bool>>
+}
+
+impl DeadSquares {
+    pub fn new(level: &Level) -> DeadSquares {
+        let (rows, cols) = level.size();
+        let comps = Component::all(level);
+        let mut goals = HashMap::<(u8, Color), Vec<Pos>>::new();
+        let mut nb_boxes = HashMap::<(u8, Color), usize>::new();
+        let mut agent_colors = Vec::new();
+
+        for goal in level.goals() {
+            if let Target::Box(letter) = goal.target {
+                goals.entry((letter, goal.color)).or_default().push(goal.pos);
+            }
+        }
+
+        for row in 0..rows {
+            for col in 0..cols {
+                let item = level[Pos::new(row as i8, col as i8)];
+                if item.is_box() { *nb_boxes.entry((item.id(), item.color())).or_insert(0) += 1; }
+                if item.is_agent() { agent_colors.push(item.color()); }
+            }
+        }
+
+        let mut dead = HashMap::new();
+
+        for (&kind, kind_goals) in &goals {
+            if nb_boxes.get(&kind).map_or(0, |&n| n) > kind_goals.len() { continue; }
+
+            let mut live = Grid::<bool>::new(rows, cols);
+            let movable = agent_colors.contains(&kind.1);
+
+            for &goal in kind_goals {
+                live[goal] = true;
+                if !movable { continue; }
+
+                if let Some(comp) = comps.iter().find(|c| c.contains(goal)) {
+                    pull_from(comp, goal, &mut live);
+                }
+            }
+
+            let mut grid = Grid::<bool>::new(rows, cols);
+            for comp in &comps {
+                for i in 0..comp.nb_free_cells() {
+                    let pos = comp.pos_of(i as i16);
+                    grid[pos] = !live[pos];
+                }
+            }
+            dead.insert(kind, grid);
+        }
+
+        DeadSquares { dead: dead }
+    }
+
+    /// Whether `item`, a box, can never reach a goal from `pos`.
+    pub fn is_dead(&self, item: Item, pos: Pos) -> bool {
+        item.is_box() && self.dead.get(&(item.id(), item.color())).is_some_and(|g| g[pos])
+    }
+
+    /// Dead cells of a box kind, None if the kind is never dead.
+    pub fn cells(&self, letter: u8, color: Color) -> Option<&Grid<bool>> {
+        self.dead.get(&(letter, color))
+    }
+
+    /// Box kinds that have dead squares.
+    pub fn kinds(&self) -> Vec<(u8, Color)> {
+        let mut kinds = self.dead.keys().cloned().collect::<Vec<(u8, Color)>>();
+        kinds.sort_by_key(|k| (k.0, k.1 as u8));
+        kinds
+    }
+}
+
+/// Marks every cell of `comp` from which a box can be moved to `goal`.
+fn pull_from(comp: &Component, goal: Pos, live: &mut Grid<bool>) {
+    let mut queue = VecDeque::new();
+    queue.push_back(goal);
+
+    while let Some(x) = queue.pop_front() {
+        for &d in &DIRS {
+            // the box arrives on x coming from y: pushed by an agent entering y from any other
+            // side, or pulled by an agent leaving x to any other side, moves can turn
+            let y = x - d;
+            if !comp.contains(y) || live[y] { continue; }
+            let pushed = DIRS.iter().any(|&e| y + e != x && comp.contains(y + e));
+            let pulled = DIRS.iter().any(|&e| x + e != y && comp.contains(x + e));
+            if pushed || pulled {
+                live[y] = true;
+                queue.push_back(y);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn notch_and_colors() {
+        let level = Level::new("red: B\n+++++++\n+0A aB+\n++ ++b+\n+++++++\n");
+        let dead = DeadSquares::new(&level);
+        let a = level[Pos::new(1, 2)];
+        let b = level[Pos::new(1, 5)];
+
+        assert!(!dead.is_dead(a, Pos::new(2, 2)));     // notch, pulled out sideways by Pull(W,S)
+        assert!(!dead.is_dead(a, Pos::new(1, 1)));     // corner, can be pulled out
+        assert!(!dead.is_dead(a, Pos::new(1, 4)));
+
+        // no red agent: B only lives on its goal
+        assert!(!dead.is_dead(b, Pos::new(2, 5)));
+        assert!(dead.is_dead(b, Pos::new(1, 5)));
+        assert_eq!(dead.kinds().len(), 2);
+    }
+
+    #[test]
+    fn open_room() {
+        // every cell of a room is live, the top row included
+        let level = Level::new("+++++\n+0A +\n+  a+\n+++++\n");
+        let dead = DeadSquares::new(&level);
+        let a = level[Pos::new(1, 2)];
+
+        for col in 1..4 {
+            assert!(!dead.is_dead(a, Pos::new(1, col)));
+            assert!(!dead.is_dead(a, Pos::new(2, col)));
+        }
+    }
+}
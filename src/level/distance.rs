@@ -0,0 +1,76 @@
+use std::collections::VecDeque;
+
+use defs::dir::DIRS;
+use defs::pos::Pos;
+use super::component::Component;
+use super::level::Level;
+
+/// Shortest path lengths between every pair of cells of a component, ignoring agents and boxes.
+#[derive(Debug, Clone)]
+pub struct Distances {
+    comp: Component,
+    data: Vec<u16>          // n×n, row major by source cell index, u16::MAX if unreachable
+}
+
+impl Distances {
+    pub fn new(comp: &Component) -> Distances {
+        let n = comp.nb_free_cells();
+        let mut data = vec!(u16::MAX; n * n);
+        let mut queue = VecDeque::new();
+
+        for src in 0..n {
+            let row = &mut data[src * n..(src + 1) * n];
+            row[src] = 0;
+            queue.push_back(src as i16);
+
+            while let Some(i) = queue.pop_front() {
+                let pos = comp.pos_of(i);
+                for &d in &DIRS {
+                    if !comp.contains(pos + d) { continue; }
+                    let j = comp.index_of(pos + d);
+                    if row[j as usize] == u16::MAX {
+                        row[j as usize] = row[i as usize] + 1;
+                        queue.push_back(j);
+                    }
+                }
+            }
+        }
+
+        Distances { comp: comp.clone(), data: data }
+    }
+
+    /// Tables of every component of the level, in the order of `Component::all`.
+    pub fn all(level: &Level) -> Vec<Distances> {
+        Component::all(level).iter().map(Distances::new).collect()
+    }
+
+    pub fn component(&self) -> &Component { &self.comp }
+
+    pub fn contains(&self, pos: Pos) -> bool { self.comp.contains(pos) }
+
+    /// Distance between two cells of the component, None if either is outside or they are
+    /// not connected.
+    pub fn between(&self, from: Pos, to: Pos) -> Option<u16> {
+        if !self.contains(from) || !self.contains(to) { return None; }
+
+        let n = self.comp.nb_free_cells();
+        let d = self.data[self.comp.index_of(from) as usize * n + self.comp.index_of(to) as usize];
+        if d == u16::MAX { None } else { Some(d) }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn distances() {
+        let level = Level::from_file("levels/easy.lvl").unwrap();
+        let tables = Distances::all(&level);
+        let d = &tables[0];
+
+        assert_eq!(d.between(Pos::new(1, 4), Pos::new(1, 4)), Some(0));
+        assert_eq!(d.between(Pos::new(1, 4), Pos::new(3, 1)), Some(5));
+        assert_eq!(d.between(Pos::new(1, 4), Pos::new(0, 0)), None);
+    }
+}
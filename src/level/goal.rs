@@ -0,0 +1,35 @@
+use defs::pos::Pos;
+use super::item::{Item, Color};
+
+/// What has to stand on a goal cell.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Target {
+    Box(u8),        // box letter, 0 for 'A'
+    Agent(u8)       // agent id
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Goal {
+    pub pos: Pos,
+    pub target: Target,
+    pub color: Color
+}
+
+impl Goal {
+    /// Goal described by a goal item of the level file. The level format only has box goals,
+    /// agent goals have to be built explicitly.
+    pub fn from_item(pos: Pos, item: Item) -> Goal {
+        Goal { pos: pos, target: Target::Box(item.id()), color: item.color() }
+    }
+
+    pub fn is_satisfied_by(&self, item: Item) -> bool {
+        match self.target {
+            Target::Box(letter) => item.is_box() && item.id() == letter && item.color() == self.color,
+            Target::Agent(id)   => item.is_agent() && item.id() == id
+        }
+    }
+
+    pub fn is_box_goal(&self) -> bool {
+        match self.target { Target::Box(_) => true, Target::Agent(_) => false }
+    }
+}
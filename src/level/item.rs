@@ -74,6 +74,9 @@ impl Item {
     pub fn is_box(&self)   -> bool { self.kind == Kind::Box }
     pub fn is_goal(&self)  -> bool { self.kind == Kind::Goal }
 
+    pub fn id(&self) -> u8 { self.id }
+    pub fn color(&self) -> Color { self.color }
+
     pub fn compatible(i: &Item, j: &Item) -> bool {
         if i.is_empty() || j.is_empty() { false }
         else if i.is_wall() || j.is_wall() { false }
@@ -7,9 +7,11 @@ use regex::Regex;
 use defs::grid::Grid;
 use defs::pos::Pos;
 use super::item::{Item, Color};
+use super::goal::Goal;
 
 pub struct Level {
-    grid: Grid<Item>
+    grid: Grid<Item>,
+    goals: Vec<Goal>
 }
 
 impl Level {
@@ -52,7 +54,15 @@ impl Level {
             }
         }
 
-        let level = Level { grid: grid };
+        let mut goals = Vec::new();
+        for col in 0..cols {
+            for row in 0..rows {
+                let pos = Pos::new(row as i8, col as i8);
+                if grid[pos].is_goal() { goals.push(Goal::from_item(pos, grid[pos])); }
+            }
+        }
+
+        let level = Level { grid: grid, goals: goals };
         level
     }
 
@@ -69,6 +79,8 @@ impl Level {
     }
 
     pub fn size(&self) -> (usize, usize) { self.grid.size() }
+    pub fn grid(&self) -> &Grid<Item> { &self.grid }
+    pub fn goals(&self) -> &[Goal] { &self.goals }
 }
 
 
@@ -97,4 +109,13 @@ impl fmt::Display for Level {
 #[cfg(test)]
 mod test {
     use super::*;
+    use level::goal::Target;
+
+    #[test]
+    fn goals() {
+        let level = Level::from_file("levels/easy.lvl").unwrap();
+        assert_eq!(level.goals().len(), 2);
+        assert_eq!(level.goals()[0].pos, Pos::new(3, 1));
+        assert_eq!(level.goals()[0].target, Target::Box(0));
+    }
 }
@@ -2,3 +2,7 @@ pub mod item;
 pub mod level;
 pub mod component;
 pub mod region;
+pub mod goal;
+pub mod distance;
+pub mod dead;
+pub mod order;
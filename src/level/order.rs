@@ -0,0 +1,190 @@
+use std::collections::VecDeque;
+
+use defs::dir::DIRS;
+use defs::pos::Pos;
+use super::component::Component;
+use super::level::Level;
+
+/// Partial order in which the goals of a level should be filled.
+///
+/// A goal on an articulation point of its component splits the component once filled: the
+/// goals cut off from the largest remaining part, deeper in a dead end or in a goal room,
+/// must be filled before it. Goals are referred to by their index in `Level::goals`.
+pub struct GoalOrder {
+    before: Vec<Vec<usize>>,
+    ranks: Vec<usize>
+}
+
+impl GoalOrder {
+    pub fn new(level: &Level) -> GoalOrder {
+        let goals = level.goals();
+        let mut before = vec!(Vec::new(); goals.len());
+
+        for comp in Component::all(level) {
+            let cuts = articulation_points(&comp);
+
+            for (g, goal) in goals.iter().enumerate() {
+                if !cuts.contains(&goal.pos) { continue; }
+
+                let labels = label_regions(&comp, goal.pos);
+                let main = largest_region(&labels);
+
+                for (h, other) in goals.iter().enumerate() {
+                    if h == g || !comp.contains(other.pos) { continue; }
+                    if labels[comp.index_of(other.pos) as usize] != main { before[g].push(h); }
+                }
+            }
+        }
+
+        let ranks = ranks(&before);
+        GoalOrder { before: before, ranks: ranks }
+    }
+
+    /// Goals that must be filled before `goal`.
+    pub fn before(&self, goal: usize) -> &[usize] { &self.before[goal] }
+
+    /// Length of the longest chain of goals to fill before `goal`, 0 for goals free to fill.
+    pub fn rank(&self, goal: usize) -> usize { self.ranks[goal] }
+
+    /// Unfilled goals whose predecessors are all filled, given which goals are `filled`.
+    pub fn ready(&self, filled: &[bool]) -> Vec<usize> {
+        (0..self.before.len())
+            .filter(|&g| !filled[g] && self.before[g].iter().all(|&h| filled[h]))
+            .collect()
+    }
+}
+
+/// Cells of `comp` whose removal disconnects it (iterative Tarjan).
+pub fn articulation_points(comp: &Component) -> Vec<Pos> {
+    let n = comp.nb_free_cells();
+    let mut order = vec!(0usize; n);       // 0 for unvisited, dfs order + 1 otherwise
+    let mut low = vec!(0usize; n);
+    let mut cut = vec!(false; n);
+    let mut counter = 0;
+
+    for root in 0..n {
+        if order[root] != 0 { continue; }
+        counter += 1;
+        order[root] = counter;
+        low[root] = counter;
+        let mut root_children = 0;
+
+        // (cell, parent, next direction to explore)
+        let mut stack = vec!((root, n, 0));
+        while let Some(&mut (cell, parent, ref mut next)) = stack.last_mut() {
+            if *next < DIRS.len() {
+                let pos = comp.pos_of(cell as i16) + DIRS[*next];
+                *next += 1;
+                if !comp.contains(pos) { continue; }
+
+                let child = comp.index_of(pos) as usize;
+                if order[child] == 0 {
+                    counter += 1;
+                    order[child] = counter;
+                    low[child] = counter;
+                    if cell == root { root_children += 1; }
+                    stack.push((child, cell, 0));
+                } else if child != parent {
+                    low[cell] = low[cell].min(order[child]);
+                }
+            } else {
+                stack.pop();
+                if parent < n {
+                    low[parent] = low[parent].min(low[cell]);
+                    if parent != root && low[cell] >= order[parent] { cut[parent] = true; }
+                }
+            }
+        }
+
+        if root_children > 1 { cut[root] = true; }
+    }
+
+    (0..n).filter(|&i| cut[i]).map(|i| comp.pos_of(i as i16)).collect()
+}
+
+// label of the part with the most cells
+fn largest_region(labels: &[usize]) -> usize {
+    let mut sizes = Vec::new();
+    for &label in labels.iter().filter(|&&l| l != usize::MAX) {
+        if label >= sizes.len() { sizes.resize(label + 1, 0); }
+        sizes[label] += 1;
+    }
+    (0..sizes.len()).max_by_key(|&l| (sizes[l], usize::MAX - l)).unwrap_or(0)
+}
+
+// labels the parts of `comp` once `removed` is walled, `usize::MAX` for `removed`
+fn label_regions(comp: &Component, removed: Pos) -> Vec<usize> {
+    let n = comp.nb_free_cells();
+    let none = usize::MAX;
+    let mut labels = vec!(none; n);
+    let mut next = 0;
+
+    for start in 0..n {
+        let pos = comp.pos_of(start as i16);
+        if labels[start] != none || pos == removed { continue; }
+
+        labels[start] = next;
+        let mut queue = VecDeque::new();
+        queue.push_back(pos);
+        while let Some(cell) = queue.pop_front() {
+            for &d in &DIRS {
+                let p = cell + d;
+                if p == removed || !comp.contains(p) { continue; }
+                let i = comp.index_of(p) as usize;
+                if labels[i] == none {
+                    labels[i] = next;
+                    queue.push_back(p);
+                }
+            }
+        }
+        next += 1;
+    }
+
+    labels
+}
+
+// longest chain of predecessors; goals caught in a cycle keep the rank reached when it is found
+fn ranks(before: &[Vec<usize>]) -> Vec<usize> {
+    let n = before.len();
+    let mut ranks = vec!(0; n);
+    for _ in 0..n {
+        let mut changed = false;
+        for g in 0..n {
+            let rank = before[g].iter().map(|&h| ranks[h] + 1).max().unwrap_or(0);
+            if rank > ranks[g] && rank <= n {
+                ranks[g] = rank;
+                changed = true;
+            }
+        }
+        if !changed { break; }
+    }
+    ranks
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn dead_end() {
+        // goals a, b, c in a dead end: c must come first, then b, then a
+        let level = Level::new("+++++++\n+0 abc+\n+ A++++\n+ B  ++\n+ C  ++\n+++++++\n");
+        let comp = &Component::all(&level)[0];
+        let cuts = articulation_points(comp);
+        assert!(cuts.contains(&Pos::new(1, 3)));
+        assert!(cuts.contains(&Pos::new(1, 4)));
+        assert!(!cuts.contains(&Pos::new(1, 5)));
+
+        let order = GoalOrder::new(&level);
+        let index = |p: Pos| level.goals().iter().position(|g| g.pos == p).unwrap();
+        let (a, b, c) = (index(Pos::new(1, 3)), index(Pos::new(1, 4)), index(Pos::new(1, 5)));
+
+        assert_eq!((order.rank(a), order.rank(b), order.rank(c)), (2, 1, 0));
+        assert!(order.before(a).contains(&b) && order.before(a).contains(&c));
+        assert_eq!(order.ready(&[false; 3]), vec!(c));
+
+        let mut filled = vec!(false; 3);
+        filled[c] = true;
+        assert_eq!(order.ready(&filled), vec!(b));
+    }
+}
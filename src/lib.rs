@@ -0,0 +1,8 @@
+extern crate regex;
+extern crate ansi_term as term;
+extern crate nalgebra as na;
+
+pub mod defs;
+pub mod level;
+pub mod state;
+pub mod search;
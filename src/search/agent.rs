@@ -0,0 +1,243 @@
+use defs::pos::{Pos, NULL_POS};
+use level::distance::Distances;
+use level::goal::Goal;
+use level::level::Level;
+use state::action::Action;
+use state::state::State;
+use super::allocation::Allocation;
+use super::graph::astar;
+use super::heuristic::Heuristic;
+use super::matching::UNREACHABLE;
+use super::problem::Problem;
+use super::reservation::Reservations;
+use super::stats::{Limits, Termination};
+
+/// Expansions allowed to each single agent search.
+pub const LOW_LEVEL_LIMIT: u64 = 50_000;
+
+/// A restriction on the plan of one agent. Times count joint actions, time t being the state
+/// before the t-th one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Constraint {
+    Vertex(usize, Pos, u32),        // the agent is not on the cell at the time
+    Edge(usize, Pos, Pos, u32),     // the agent does not move between the cells at the step
+    BoxCell(usize, Pos, u32)        // no box of the agent is on the cell at the time
+}
+
+impl Constraint {
+    pub fn agent(&self) -> usize {
+        match *self {
+            Constraint::Vertex(a, _, _) | Constraint::Edge(a, _, _, _) | Constraint::BoxCell(a, _, _) => a
+        }
+    }
+
+    // orders constraint sets, to recognize the same set reached in another order
+    pub fn key(&self) -> (usize, u32, u8, (i8, i8), (i8, i8)) {
+        match *self {
+            Constraint::Vertex(a, c, t)     => (a, t, 0, (c.row, c.col), (c.row, c.col)),
+            Constraint::Edge(a, f, c, t)    => (a, t, 1, (f.row, f.col), (c.row, c.col)),
+            Constraint::BoxCell(a, c, t)    => (a, t, 2, (c.row, c.col), (c.row, c.col))
+        }
+    }
+
+    pub fn time(&self) -> u32 {
+        match *self {
+            Constraint::Vertex(_, _, t) | Constraint::BoxCell(_, _, t) => t,
+            Constraint::Edge(_, _, _, t) => t + 1
+        }
+    }
+}
+
+/// One agent planned on its own, for the multi-agent planners: its goals, and the level as
+/// it sees it, without the other agents and their boxes. The planners keep them out of its way
+/// with constraints or reservations.
+pub struct AgentTask {
+    pub id: usize,
+    pub world: State,
+    pub goals: Vec<Goal>
+}
+
+impl AgentTask {
+    /// Tasks of every agent id of `start`, None for missing ids.
+    pub fn all(level: &Level, start: &State, allocation: &Allocation) -> Vec<Option<AgentTask>> {
+        (0..start.nb_agents()).map(|a| {
+            if start.agent(a) == NULL_POS { return None; }
+            let goals = level.goals().iter().zip(&allocation.goals)
+                .filter(|&(_, &owner)| owner == Some(a)).map(|(g, _)| *g).collect();
+            Some(AgentTask { id: a, world: world(start, a, allocation), goals: goals })
+        }).collect()
+    }
+
+    /// Shortest plan filling the goals, honoring `constraints` and avoiding the cells that
+    /// `reservations` hold for other agents, without trailing NoOps. Fails with the reason the
+    /// search stopped.
+    pub fn plan(&self, constraints: &[Constraint], reservations: Option<&Reservations>, tables: &[Distances],
+                limits: &Limits) -> Result<Vec<Action>, Termination> {
+        let horizon = constraints.iter().map(|c| c.time()).max().unwrap_or(0)
+            .max(reservations.map_or(0, |r| r.horizon()));
+        let problem = AgentProblem {
+            agent: self,
+            constraints: constraints,
+            reservations: reservations,
+            horizon: horizon,
+            actions: Action::all()
+        };
+        let h = AgentHeuristic { agent: self, tables: tables };
+
+        let outcome = astar(&problem, &h, limits);
+        let termination = outcome.stats.termination.unwrap_or(Termination::Exhausted);
+        outcome.solution.map(|s| {
+            let mut plan = s.plan;
+            while plan.last() == Some(&Action::NoOp) { plan.pop(); }
+            plan
+        }).ok_or(termination)
+    }
+
+    /// Cells held by the agent and its boxes in `state`, a state of its world.
+    pub fn objects(&self, state: &State) -> Vec<Pos> {
+        let agent = state.agent(self.id);
+        let color = state[agent].color();
+        let (rows, cols) = state.size();
+
+        let mut cells = vec!(agent);
+        for row in 0..rows {
+            for col in 0..cols {
+                let pos = Pos::new(row as i8, col as i8);
+                if state[pos].is_box() && state[pos].color() == color { cells.push(pos); }
+            }
+        }
+        cells
+    }
+}
+
+// the start state as seen by `agent`: the other agents and their boxes are gone
+fn world(start: &State, agent: usize, allocation: &Allocation) -> State {
+    let mut world = start.clone();
+    for (other, &pos) in start.agents().iter().enumerate() {
+        if other != agent && pos != NULL_POS { world.remove(pos); }
+    }
+
+    let (rows, cols) = start.size();
+    for row in 0..rows {
+        for col in 0..cols {
+            let pos = Pos::new(row as i8, col as i8);
+            if allocation.boxes.get(&pos).is_some_and(|&owner| owner != agent) { world.remove(pos); }
+        }
+    }
+    world
+}
+
+/// Space-time search of one agent. Time stops counting after the last constraint or
+/// reservation change, which keeps the space finite.
+struct AgentProblem<'a> {
+    agent: &'a AgentTask,
+    constraints: &'a [Constraint],
+    reservations: Option<&'a Reservations>,
+    horizon: u32,
+    actions: Vec<Action>
+}
+
+impl<'a> AgentProblem<'a> {
+    fn allowed(&self, from: &State, to: &State, step: u32) -> bool {
+        let id = self.agent.id;
+        let (a, b) = (from.agent(id), to.agent(id));
+        let color = from[a].color();
+
+        let constrained = self.constraints.iter().all(|c| match *c {
+            Constraint::Vertex(_, cell, t)        => t != step + 1 || b != cell,
+            Constraint::Edge(_, src, dst, t)      => t != step || a != src || b != dst,
+            Constraint::BoxCell(_, cell, t)       => t != step + 1 || !(to[cell].is_box() && to[cell].color() == color)
+        });
+        if !constrained { return false; }
+
+        // a cell cannot be entered at the step it is left, so other objects must be off the
+        // cells of ours for one step on each side
+        self.reservations.is_none_or(|r| {
+            self.agent.objects(to).iter().all(|&c| (step..step + 3).all(|t| r.is_free(c, t, id)))
+        })
+    }
+}
+
+impl<'a> Problem for AgentProblem<'a> {
+    type Node = (State, u32);
+    type Action = Action;
+    type Key = (State, u32);
+
+    fn initial(&self) -> Vec<(State, u32)> {
+        let start = self.agent.world.clone();
+        let constrained = self.constraints.iter().all(|c| match *c {
+            Constraint::Vertex(_, cell, 0)  => start.agent(self.agent.id) != cell,
+            Constraint::BoxCell(_, cell, 0) => !start[cell].is_box(),
+            _ => true
+        });
+        let reserved = self.reservations.is_some_and(|r| {
+            self.agent.objects(&start).iter().any(|&c| !r.is_free(c, 0, self.agent.id) || !r.is_free(c, 1, self.agent.id))
+        });
+        if constrained && !reserved { vec!((start, 0)) } else { Vec::new() }
+    }
+
+    fn is_goal(&self, &(ref state, t): &(State, u32)) -> bool {
+        t >= self.horizon && self.agent.goals.iter().all(|g| state.satisfies(g))
+    }
+
+    fn expand(&self, &(ref state, t): &(State, u32), out: &mut Vec<(Action, (State, u32), u32)>) {
+        for &action in &self.actions {
+            if let Some(next) = state.apply(self.agent.id, action) {
+                if !self.allowed(state, &next, t) { continue; }
+                out.push((action, (next, (t + 1).min(self.horizon + 1)), 1));
+            }
+        }
+    }
+
+    fn node_size(&self, node: &(State, u32)) -> usize { node.0.mem_size() }
+
+    fn key(&self, node: &(State, u32)) -> (State, u32) { node.clone() }
+
+    fn key_size(&self, key: &(State, u32)) -> usize { key.0.mem_size() }
+}
+
+/// Sum over unfilled box goals of the distance of the closest box that fits, plus the walk of
+/// the agent to the closest of those boxes: each step moves at most one box by one cell, and
+/// the agent has to reach a box before moving it. The distance of the agent to its own goal
+/// is taken instead when larger, a push may bring both closer.
+struct AgentHeuristic<'a> {
+    agent: &'a AgentTask,
+    tables: &'a [Distances]
+}
+
+impl<'a> Heuristic<(State, u32)> for AgentHeuristic<'a> {
+    fn estimate(&self, (state, _): &(State, u32)) -> u32 {
+        let (rows, cols) = state.size();
+        let mut boxes = Vec::new();
+        for row in 0..rows {
+            for col in 0..cols {
+                let pos = Pos::new(row as i8, col as i8);
+                if state[pos].is_box() { boxes.push(pos); }
+            }
+        }
+
+        let agent = state.agent(self.agent.id);
+        let mut total = 0;
+        let mut walk = None;
+        let mut own_goal = 0;
+        for goal in self.agent.goals.iter().filter(|g| !state.satisfies(g)) {
+            let table = self.tables.iter().find(|t| t.contains(goal.pos));
+            if !goal.is_box_goal() {
+                own_goal = table.and_then(|t| t.between(agent, goal.pos)).map_or(UNREACHABLE, |d| d as u32);
+                continue;
+            }
+            let best = boxes.iter()
+                .filter(|&&p| goal.is_satisfied_by(state[p]))
+                .filter_map(|&p| table.and_then(|t| t.between(p, goal.pos)))
+                .min();
+            total += best.map_or(UNREACHABLE, |d| d as u32);
+
+            let reach = boxes.iter()
+                .filter(|&&p| goal.is_satisfied_by(state[p]) && p != goal.pos)
+                .filter_map(|&p| table.and_then(|t| t.between(agent, p)))
+                .min();
+            if let Some(d) = reach { walk = Some(walk.map_or(d, |w: u16| w.min(d))); }
+        }
+        (total + walk.map_or(0, |d| (d as u32).saturating_sub(1))).max(own_goal)
+    }
+}
@@ -0,0 +1,291 @@
+use std::collections::HashMap;
+
+use na::core::DMatrix;
+
+use defs::assignment::min_cost_assignment;
+use defs::pos::{Pos, NULL_POS};
+use level::distance::Distances;
+use level::goal::Target;
+use level::item::Color;
+use level::level::Level;
+use state::state::State;
+use super::matching::UNREACHABLE;
+
+/// Which agent moves each box and fills each goal. Boxes without an agent of their color are
+/// owned by nobody and never move.
+#[derive(Debug, Clone)]
+pub struct Allocation {
+    pub boxes: HashMap<Pos, usize>,
+    pub goals: Vec<Option<usize>>      // indexed like `Level::goals`
+}
+
+impl Allocation {
+    /// Owners given by `tasks`. The boxes of no task go to the closest agent of their color,
+    /// who is the one to move them out of the way.
+    pub fn new(start: &State, tables: &[Distances], tasks: &Tasks) -> Allocation {
+        let agents = agents(start);
+        let mut boxes = HashMap::new();
+        let mut goals = vec!(None; tasks.nb_goals);
+
+        for (a, list) in tasks.agents.iter().enumerate() {
+            for task in list {
+                goals[task.goal] = Some(a);
+                if let Some(pos) = task.box_pos { boxes.insert(pos, a); }
+            }
+        }
+
+        for pos in all_boxes(start) {
+            if boxes.contains_key(&pos) { continue; }
+            let closest = agents.iter().filter(|&&(_, p, c)| c == start[pos].color() && distance(tables, p, pos).is_some())
+                .min_by_key(|&&(_, p, _)| distance(tables, p, pos));
+            if let Some(&(a, _, _)) = closest { boxes.insert(pos, a); }
+        }
+
+        Allocation { boxes: boxes, goals: goals }
+    }
+}
+
+/// Filling one goal: the box to bring there, none for agent goals, and the estimated cost for
+/// the agent, walk to the box included.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Task {
+    pub goal: usize,                // index in `Level::goals`
+    pub box_pos: Option<Pos>,
+    pub cost: u32
+}
+
+/// Tasks of each agent, in the order it should do them, and the goals nobody can fill.
+#[derive(Debug, Clone)]
+pub struct Tasks {
+    pub agents: Vec<Vec<Task>>,
+    pub unassigned: Vec<usize>,
+    nb_goals: usize
+}
+
+impl Tasks {
+    /// Boxes are matched to goals with minimal total distance, then the (box, goal) pairs of
+    /// each color to the agents of that color, again with minimal total cost. Each agent takes
+    /// at most its share of the pairs of its color, rounded up, which balances the load.
+    pub fn hungarian(level: &Level, start: &State, tables: &[Distances]) -> Tasks {
+        let mut tasks = Tasks::settled(level, start, tables);
+        let agents = agents(start);
+
+        // box goals left, per kind, and the boxes that may fill them
+        let mut kinds = HashMap::<(u8, Color), (Vec<usize>, Vec<Pos>)>::new();
+        for (g, goal) in level.goals().iter().enumerate() {
+            if let Target::Box(letter) = goal.target {
+                if !tasks.is_assigned(g) { kinds.entry((letter, goal.color)).or_default().0.push(g); }
+            }
+        }
+        for pos in tasks.free_boxes(start) {
+            if let Some(kind) = kinds.get_mut(&(start[pos].id(), start[pos].color())) { kind.1.push(pos); }
+        }
+
+        let mut pairs = HashMap::<Color, Vec<(usize, Pos, u32)>>::new();
+        for (&(_, color), (goals, boxes)) in &kinds {
+            let cost = DMatrix::from_fn(goals.len(), boxes.len(), |g, b| {
+                distance(tables, boxes[b], level.goals()[goals[g]].pos).unwrap_or(UNREACHABLE)
+            });
+            let (assignment, _) = min_cost_assignment(&cost);
+
+            for (g, b) in assignment.into_iter().enumerate() {
+                match b {
+                    Some(b) if cost[(g, b)] < UNREACHABLE => {
+                        pairs.entry(color).or_default().push((goals[g], boxes[b], cost[(g, b)]));
+                    }
+                    _ => tasks.unassigned.push(goals[g])
+                }
+            }
+        }
+
+        for (color, pairs) in pairs {
+            let own = agents.iter().filter(|&&(_, _, c)| c == color).cloned().collect::<Vec<(usize, Pos, Color)>>();
+            if own.is_empty() {
+                tasks.unassigned.extend(pairs.iter().map(|&(g, _, _)| g));
+                continue;
+            }
+
+            let share = pairs.len().div_ceil(own.len());
+            let cost = DMatrix::from_fn(pairs.len(), own.len() * share, |t, s| {
+                let (_, b, d) = pairs[t];
+                walk(tables, own[s / share].1, b).map_or(UNREACHABLE, |w| w + d)
+            });
+            let (assignment, _) = min_cost_assignment(&cost);
+
+            for (t, s) in assignment.into_iter().enumerate() {
+                let (g, b, _) = pairs[t];
+                match s {
+                    Some(s) if cost[(t, s)] < UNREACHABLE => {
+                        tasks.agents[own[s / share].0].push(Task { goal: g, box_pos: Some(b), cost: cost[(t, s)] });
+                    }
+                    _ => tasks.unassigned.push(g)
+                }
+            }
+        }
+
+        for list in &mut tasks.agents { list.sort_by_key(|t| t.cost); }
+        tasks.unassigned.sort();
+        tasks
+    }
+
+    /// Greedy auction: each round, every agent bids on every (box, goal) pair of its color
+    /// the load it already has plus the cost of the pair from where its last task left it,
+    /// and the lowest bid wins. Tasks come out in the order the agent won them.
+    pub fn auction(level: &Level, start: &State, tables: &[Distances]) -> Tasks {
+        let mut tasks = Tasks::settled(level, start, tables);
+        let agents = agents(start);
+        let mut free = tasks.free_boxes(start);
+        let mut open = level.goals().iter().enumerate()
+            .filter(|&(g, goal)| goal.is_box_goal() && !tasks.is_assigned(g)).map(|(g, _)| g).collect::<Vec<usize>>();
+
+        let mut positions = agents.iter().map(|&(_, p, _)| p).collect::<Vec<Pos>>();
+        let mut loads = agents.iter().map(|&(a, _, _)| tasks.agents[a].iter().map(|t| t.cost).sum()).collect::<Vec<u32>>();
+
+        loop {
+            let mut best = None;
+            for (i, &(_, _, color)) in agents.iter().enumerate() {
+                for (k, &g) in open.iter().enumerate() {
+                    let goal = level.goals()[g];
+                    if goal.color != color { continue; }
+                    for (j, &b) in free.iter().enumerate() {
+                        if !goal.is_satisfied_by(start[b]) { continue; }
+                        let cost = match (walk(tables, positions[i], b), distance(tables, b, goal.pos)) {
+                            (Some(w), Some(d)) => w + d,
+                            _ => continue
+                        };
+                        if best.is_none_or(|(bid, _, _, _, _)| loads[i] + cost < bid) {
+                            best = Some((loads[i] + cost, cost, i, k, j));
+                        }
+                    }
+                }
+            }
+
+            let (_, cost, i, k, j) = match best { Some(b) => b, None => break };
+            let (g, b) = (open.remove(k), free.remove(j));
+            tasks.agents[agents[i].0].push(Task { goal: g, box_pos: Some(b), cost: cost });
+            loads[i] += cost;
+            positions[i] = level.goals()[g].pos;
+        }
+
+        tasks.unassigned.extend(open);
+        tasks.unassigned.sort();
+        tasks
+    }
+
+    /// Sum of the task costs.
+    pub fn cost(&self) -> u32 {
+        self.agents.iter().flat_map(|l| l.iter()).map(|t| t.cost).sum()
+    }
+
+    /// Largest sum of task costs of an agent.
+    pub fn makespan(&self) -> u32 {
+        self.agents.iter().map(|l| l.iter().map(|t| t.cost).sum()).max().unwrap_or(0)
+    }
+
+    /// Tasks that need no decision: agent goals, and box goals already filled, whose box goes
+    /// to the closest agent of its color.
+    fn settled(level: &Level, start: &State, tables: &[Distances]) -> Tasks {
+        let agents = agents(start);
+        let mut tasks = Tasks { agents: vec!(Vec::new(); start.nb_agents()), unassigned: Vec::new(), nb_goals: level.goals().len() };
+
+        for (g, goal) in level.goals().iter().enumerate() {
+            match goal.target {
+                Target::Agent(id) => {
+                    let id = id as usize;
+                    match agents.iter().find(|&&(a, _, _)| a == id).and_then(|&(_, p, _)| distance(tables, p, goal.pos)) {
+                        Some(d) => tasks.agents[id].push(Task { goal: g, box_pos: None, cost: d }),
+                        None => tasks.unassigned.push(g)
+                    }
+                }
+                Target::Box(_) if goal.is_satisfied_by(start[goal.pos]) => {
+                    let closest = agents.iter().filter(|&&(_, p, c)| c == goal.color && distance(tables, p, goal.pos).is_some())
+                        .min_by_key(|&&(_, p, _)| distance(tables, p, goal.pos));
+                    if let Some(&(a, _, _)) = closest {
+                        tasks.agents[a].push(Task { goal: g, box_pos: Some(goal.pos), cost: 0 });
+                    }
+                }
+                Target::Box(_) => {}
+            }
+        }
+        tasks
+    }
+
+    fn is_assigned(&self, goal: usize) -> bool {
+        self.unassigned.contains(&goal) || self.agents.iter().any(|l| l.iter().any(|t| t.goal == goal))
+    }
+
+    // boxes of no task yet
+    fn free_boxes(&self, start: &State) -> Vec<Pos> {
+        all_boxes(start).into_iter()
+            .filter(|&p| !self.agents.iter().any(|l| l.iter().any(|t| t.box_pos == Some(p))))
+            .collect()
+    }
+}
+
+fn agents(start: &State) -> Vec<(usize, Pos, Color)> {
+    start.agents().iter().enumerate()
+        .filter(|&(_, &p)| p != NULL_POS).map(|(a, &p)| (a, p, start[p].color())).collect()
+}
+
+fn all_boxes(start: &State) -> Vec<Pos> {
+    let (rows, cols) = start.size();
+    let mut boxes = Vec::new();
+    for row in 0..rows {
+        for col in 0..cols {
+            let pos = Pos::new(row as i8, col as i8);
+            if start[pos].is_box() { boxes.push(pos); }
+        }
+    }
+    boxes
+}
+
+fn distance(tables: &[Distances], a: Pos, b: Pos) -> Option<u32> {
+    tables.iter().find(|t| t.contains(a) && t.contains(b)).and_then(|t| t.between(a, b)).map(|d| d as u32)
+}
+
+// steps for an agent to get next to a box
+fn walk(tables: &[Distances], agent: Pos, b: Pos) -> Option<u32> {
+    distance(tables, agent, b).map(|d| d.saturating_sub(1))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn shared_color() {
+        // two red agents, four boxes: each agent takes the two on its side
+        let level = Level::new("red: 0, 1, A\n++++++++++++\n+0A a  a A1+\n+ A a  a A +\n++++++++++++\n");
+        let start = State::new(&level);
+        let tables = Distances::all(&level);
+
+        let hungarian = Tasks::hungarian(&level, &start, &tables);
+        assert_eq!((hungarian.cost(), hungarian.makespan()), (10, 5));
+
+        // the auction sends the agents to the farther boxes from the goals they just filled
+        let auction = Tasks::auction(&level, &start, &tables);
+        assert_eq!((auction.cost(), auction.makespan()), (12, 6));
+
+        for tasks in [hungarian, auction] {
+            assert!(tasks.unassigned.is_empty());
+            assert_eq!(tasks.agents[0].len(), 2);
+            assert_eq!(tasks.agents[1].len(), 2);
+            assert!(tasks.agents[0].iter().all(|t| t.box_pos.unwrap().col < 6));
+
+            let allocation = Allocation::new(&start, &tables, &tasks);
+            assert_eq!(allocation.boxes.len(), 4);
+            assert!(allocation.goals.iter().all(|g| g.is_some()));
+        }
+    }
+
+    #[test]
+    fn unassigned_goals() {
+        // no green agent, and a blue goal without a box
+        let level = Level::new("red: 0, A\ngreen: B\n+++++++\n+0A ab+\n+B   a+\n+++++++\n");
+        let start = State::new(&level);
+        let tables = Distances::all(&level);
+        let tasks = Tasks::hungarian(&level, &start, &tables);
+        assert_eq!(tasks.unassigned.len(), 2);
+        assert_eq!(tasks.agents[0].len(), 1);
+    }
+}
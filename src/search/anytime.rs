@@ -0,0 +1,89 @@
+use super::graph::{weighted_astar, Solution};
+use super::heuristic::Heuristic;
+use super::problem::Problem;
+use super::stats::{Limits, Outcome, SearchStats, Termination};
+
+/// Weights tried in turn by `anytime`, ending with plain A*.
+pub const WEIGHTS: [f64; 5] = [5.0, 3.0, 2.0, 1.5, 1.0];
+
+/// Restarting weighted A*: runs weighted A* with decreasing `weights`, each run pruned by the
+/// cost of the best plan found so far, and calls `improved` with every better plan and the
+/// weight that found it. Stops when a limit is hit or when the last weight has run to
+/// completion, in which case the returned plan is optimal if the last weight is 1 and the
+/// heuristic is admissible.
+///
+/// The deadline and the expansion limit apply to the whole run, the memory limit to each
+/// weighted A* run.
+pub fn anytime<P, H, F>(problem: &P, heuristic: &H, weights: &[f64], limits: &Limits, mut improved: F)
+    -> Outcome<Solution<P::Action>>
+    where P: Problem, H: Heuristic<P::Node>, F: FnMut(&Solution<P::Action>, f64)
+{
+    let mut best: Option<Solution<P::Action>> = None;
+    let mut total: Option<SearchStats> = None;
+    let mut termination = Termination::Exhausted;
+
+    for &w in weights {
+        let mut run_limits = limits.clone();
+        if let Some(ref s) = best { run_limits.max_cost = Some(s.cost); }
+        if let (Some(n), Some(t)) = (limits.max_expanded, total.as_ref()) {
+            run_limits.max_expanded = Some(n.saturating_sub(t.expanded));
+        }
+
+        let outcome = weighted_astar(problem, heuristic, w, &run_limits);
+        let stats = outcome.stats;
+        termination = stats.termination.unwrap_or(Termination::Exhausted);
+
+        total = Some(match total {
+            None => stats,
+            Some(t) => SearchStats {
+                expanded: t.expanded + stats.expanded,
+                generated: t.generated + stats.generated,
+                frontier_peak: t.frontier_peak.max(stats.frontier_peak),
+                closed: t.closed.max(stats.closed),
+                memory: t.memory.max(stats.memory),
+                elapsed: t.elapsed + stats.elapsed,
+                termination: stats.termination
+            }
+        });
+
+        if let Some(solution) = outcome.solution {
+            improved(&solution, w);
+            best = Some(solution);
+        }
+
+        if termination != Termination::Solved && termination != Termination::Exhausted { break; }
+    }
+
+    let mut stats = total.expect("no weight given");
+    stats.termination = Some(match termination {
+        Termination::Exhausted if best.is_some() => Termination::Solved,
+        t => t
+    });
+
+    Outcome { solution: best, stats: stats }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use level::level::Level;
+    use search::graph::bfs;
+    use search::heuristic::GoalCount;
+    use search::problem::LevelProblem;
+
+    #[test]
+    fn improves_to_optimal() {
+        let level = Level::from_file("levels/easy.lvl").unwrap();
+        let problem = LevelProblem::new(&level);
+        let optimal = bfs(&problem, &Limits::new()).solution.unwrap().cost;
+
+        let mut costs = Vec::new();
+        let outcome = anytime(&problem, &GoalCount::new(&level), &WEIGHTS, &Limits::new(),
+                              |s, _| costs.push(s.cost));
+
+        assert_eq!(outcome.solution.unwrap().cost, optimal);
+        assert_eq!(outcome.stats.termination, Some(Termination::Solved));
+        assert!(costs.windows(2).all(|c| c[1] < c[0]));
+        assert_eq!(*costs.last().unwrap(), optimal);
+    }
+}
@@ -0,0 +1,356 @@
+use std::collections::HashMap;
+use std::mem::size_of;
+
+use defs::dir::DIRS;
+use defs::grid::Grid;
+use defs::pos::{Pos, NULL_POS};
+use level::goal::Target;
+use level::item::{Item, Color};
+use state::packed::PackedState;
+use state::state::State;
+use super::graph::{self, Solution};
+use super::problem::{LevelProblem, Problem, Step};
+use super::stats::{Limits, Monitor, Outcome, Termination};
+
+/// Maximum number of agent placements used as starts of the backward search.
+pub const MAX_PLACEMENTS: usize = 1000;
+
+/// The level searched backwards, from the goal configurations to the start.
+///
+/// Every action has an inverse (pushes and pulls swap), so the predecessors of a state are its
+/// forward successors, labelled with the inverse actions. Plans found on this problem list the
+/// forward steps from the goal back to the start; reversed, they solve the level.
+pub struct Backward<'a> {
+    problem: &'a LevelProblem<'a>,
+    goals: Vec<State>,
+    complete: bool
+}
+
+impl<'a> Backward<'a> {
+    pub fn new(problem: &'a LevelProblem<'a>) -> Backward<'a> {
+        let (goals, complete) = goal_states(problem, MAX_PLACEMENTS);
+        Backward { problem: problem, goals: goals, complete: complete }
+    }
+
+    // a search from the goals that finds nothing proves the level unsolvable only if they are
+    // all the goal configurations
+    fn finished(&self, termination: Termination) -> Termination {
+        if termination == Termination::Exhausted && !self.complete { Termination::Incomplete } else { termination }
+    }
+}
+
+impl<'a> Problem for Backward<'a> {
+    type Node = State;
+    type Action = Step;
+    type Key = PackedState;
+
+    fn initial(&self) -> Vec<State> { self.goals.clone() }
+
+    fn is_goal(&self, state: &State) -> bool { state == self.problem.start() }
+
+    fn expand(&self, state: &State, out: &mut Vec<(Step, State, u32)>) {
+        for agent in 0..state.nb_agents() {
+            if state.agent(agent) == NULL_POS { continue; }
+            for &action in self.problem.actions() {
+                if let Some(next) = state.apply(agent, action) { out.push(((agent, action.inverse()), next, 1)); }
+            }
+        }
+    }
+
+    fn node_size(&self, node: &State) -> usize { node.mem_size() }
+
+    fn key(&self, state: &State) -> PackedState { self.problem.key(state) }
+
+    fn key_size(&self, key: &PackedState) -> usize { key.mem_size() }
+}
+
+/// Goal configurations of the level, with the agents without goals on every combination of
+/// free cells they can reach, at most `max` of them, and whether they are all of them.
+///
+/// Each box goal gets a box of its letter and color. When a kind has more boxes than goals,
+/// the extra boxes stay where they start, off the goals; boxes of kinds without goals do not
+/// move either. Such pinned boxes, or more than `max` placements, leave goal configurations
+/// out. Returns no state when the goals cannot all be filled.
+pub fn goal_states(problem: &LevelProblem, max: usize) -> (Vec<State>, bool) {
+    let level = problem.level();
+    let start = problem.start();
+    let (rows, cols) = start.size();
+    let mut state = start.clone();
+
+    let mut goals = HashMap::<(u8, Color), Vec<Pos>>::new();
+    for goal in level.goals() {
+        if let Target::Box(letter) = goal.target {
+            goals.entry((letter, goal.color)).or_default().push(goal.pos);
+        }
+    }
+
+    let mut boxes = HashMap::<(u8, Color), Vec<Pos>>::new();
+    for row in 0..rows {
+        for col in 0..cols {
+            let pos = Pos::new(row as i8, col as i8);
+            let item = state[pos];
+            if item.is_agent() { state.remove(pos); }
+            if item.is_box() { boxes.entry((item.id(), item.color())).or_default().push(pos); }
+        }
+    }
+
+    // every box of a kind with goals is lifted, but the extra ones not on a goal of theirs
+    let mut pinned = false;
+    for (kind, cells) in &goals {
+        let mut kind_boxes = boxes.remove(kind).unwrap_or_default();
+        if kind_boxes.len() < cells.len() { return (Vec::new(), true); }
+
+        kind_boxes.sort_by_key(|p| cells.contains(p));
+        let extra = kind_boxes.len() - cells.len();
+        for &pos in &kind_boxes[extra..] { state.remove(pos); }
+        pinned |= extra > 0;
+    }
+    pinned |= !boxes.is_empty();
+
+    for (&(letter, color), cells) in &goals {
+        for &pos in cells {
+            if !state.is_free(pos) { return (Vec::new(), !pinned); }
+            state.place(pos, Item::new(b'A' + letter, color));
+        }
+    }
+
+    // agents with a goal go there, the others anywhere in reach
+    let mut free_agents = Vec::new();
+    for (id, &pos) in start.agents().iter().enumerate() {
+        if pos == NULL_POS { continue; }
+        let item = start[pos];
+        match level.goals().iter().find(|g| g.target == Target::Agent(id as u8)) {
+            Some(goal) => {
+                if !state.is_free(goal.pos) { return (Vec::new(), !pinned); }
+                state.place(goal.pos, item);
+            }
+            None => free_agents.push((pos, item))
+        }
+    }
+
+    let mut placements = Vec::new();
+    place_agents(&state, level.grid(), &free_agents, max + 1, &mut placements);
+    let truncated = placements.len() > max;
+    placements.truncate(max);
+    (placements, !pinned && !truncated)
+}
+
+// every way to put `agents` on free cells connected to their start by non wall cells
+fn place_agents(state: &State, grid: &Grid<Item>, agents: &[(Pos, Item)], max: usize, out: &mut Vec<State>) {
+    let (&(start, item), rest) = match agents.split_first() {
+        Some(split) => split,
+        None => { out.push(state.clone()); return; }
+    };
+
+    let reach = wall_reach(grid, start);
+    let (rows, cols) = state.size();
+    for row in 0..rows {
+        for col in 0..cols {
+            let pos = Pos::new(row as i8, col as i8);
+            if out.len() >= max { return; }
+            if !reach[pos] || !state.is_free(pos) { continue; }
+
+            let mut next = state.clone();
+            next.place(pos, item);
+            place_agents(&next, grid, rest, max, out);
+        }
+    }
+}
+
+fn wall_reach(grid: &Grid<Item>, from: Pos) -> Grid<bool> {
+    let (rows, cols) = grid.size();
+    let mut reach = Grid::<bool>::new(rows, cols);
+    let mut stack = vec!(from);
+    reach[from] = true;
+    while let Some(cell) = stack.pop() {
+        for &d in &DIRS {
+            let p = cell + d;
+            if p.row < 0 || p.col < 0 || p.row as usize >= rows || p.col as usize >= cols { continue; }
+            if !grid[p].is_wall() && !reach[p] { reach[p] = true; stack.push(p); }
+        }
+    }
+    reach
+}
+
+/// Breadth first search from the goal configurations back to the start.
+pub fn reverse(problem: &LevelProblem, limits: &Limits) -> Outcome<Solution<Step>> {
+    let backward = Backward::new(problem);
+    let mut outcome = graph::bfs(&backward, limits);
+    if let Some(ref mut solution) = outcome.solution { solution.plan.reverse(); }
+    outcome.stats.termination = outcome.stats.termination.map(|t| backward.finished(t));
+    outcome
+}
+
+struct Side<'p> {
+    problem: &'p dyn Problem<Node=State, Action=Step, Key=PackedState>,
+    records: Vec<(State, Option<usize>, Option<Step>, u32)>,   // node, parent, step, depth
+    seen: HashMap<PackedState, usize>,     // both sides pack with the problem's encoder
+    layer: Vec<usize>
+}
+
+impl<'p> Side<'p> {
+    fn new(problem: &'p dyn Problem<Node=State, Action=Step, Key=PackedState>) -> Side<'p> {
+        let mut side = Side { problem: problem, records: Vec::new(), seen: HashMap::new(), layer: Vec::new() };
+        for node in problem.initial() {
+            let key = problem.key(&node);
+            if side.seen.contains_key(&key) { continue; }
+            side.seen.insert(key, side.records.len());
+            side.layer.push(side.records.len());
+            side.records.push((node, None, None, 0));
+        }
+        side
+    }
+
+    // steps from the root to `i`
+    fn path(&self, mut i: usize) -> Vec<Step> {
+        let mut plan = Vec::new();
+        while let (_, Some(parent), Some(step), _) = self.records[i] {
+            plan.push(step);
+            i = parent;
+        }
+        plan.reverse();
+        plan
+    }
+}
+
+/// Bidirectional breadth first search: the smaller of the forward and backward frontiers is
+/// expanded one whole layer at a time, until a node of one side is generated by the other.
+/// The plan is the shortest one through the meeting layer, so it is optimal among plans
+/// ending in one of the `goal_states`. When those are not all the goal configurations, finding
+/// nothing ends as `Incomplete` rather than `Exhausted`.
+pub fn bidirectional(problem: &LevelProblem, limits: &Limits) -> Outcome<Solution<Step>> {
+    let backward = Backward::new(problem);
+    let mut monitor = Monitor::new(limits);
+    let mut sides = [Side::new(problem), Side::new(&backward)];
+    let start = problem.key(problem.start());
+    let node_size = problem.start().mem_size() + start.mem_size() + size_of::<(State, Option<usize>, Option<Step>, u32)>();
+
+    if let Some(&b) = sides[1].seen.get(&start) {
+        let plan = sides[1].path(b).into_iter().rev().collect::<Vec<Step>>();
+        return monitor.finish(Termination::Solved, Some(Solution { cost: plan.len() as u32, plan: plan }));
+    }
+
+    let mut children = Vec::new();
+    loop {
+        if sides[0].layer.is_empty() || sides[1].layer.is_empty() {
+            return monitor.finish(backward.finished(Termination::Exhausted), None);
+        }
+
+        let s = if sides[0].layer.len() <= sides[1].layer.len() { 0 } else { 1 };
+        let layer = std::mem::take(&mut sides[s].layer);
+        let mut best: Option<(u32, usize, usize)> = None;     // cost, record on side s, record on the other
+
+        for (k, &i) in layer.iter().enumerate() {
+            let open = layer.len() - k + sides[s].layer.len() + sides[1 - s].layer.len();
+            let closed = sides[0].records.len() + sides[1].records.len();
+            if let Some(t) = monitor.expand(open, closed, closed * node_size) {
+                return monitor.finish(t, None);
+            }
+
+            sides[s].problem.expand(&sides[s].records[i].0, &mut children);
+            monitor.generated(children.len());
+            let depth = sides[s].records[i].3 + 1;
+
+            for (step, child, _) in children.drain(..) {
+                let key = problem.key(&child);
+                if sides[s].seen.contains_key(&key) { continue; }
+                let j = sides[s].records.len();
+
+                if let Some(&o) = sides[1 - s].seen.get(&key) {
+                    let cost = depth + sides[1 - s].records[o].3;
+                    if best.is_none_or(|b| cost < b.0) { best = Some((cost, j, o)); }
+                }
+
+                sides[s].seen.insert(key, j);
+                sides[s].records.push((child, Some(i), Some(step), depth));
+                sides[s].layer.push(j);
+            }
+        }
+
+        if let Some((cost, j, o)) = best {
+            let (f, b) = if s == 0 { (j, o) } else { (o, j) };
+            let mut plan = sides[0].path(f);
+            plan.extend(sides[1].path(b).into_iter().rev());
+            return monitor.finish(Termination::Solved, Some(Solution { plan: plan, cost: cost }));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use level::level::Level;
+    use search::graph::bfs;
+    use state::action::Action;
+
+    fn check(problem: &LevelProblem, solution: &Solution<Step>) {
+        let mut state = problem.start().clone();
+        for &(agent, action) in &solution.plan {
+            state = state.apply(agent, action).expect("inapplicable step");
+        }
+        assert!(state.is_goal_state(problem.level()));
+        assert_eq!(solution.plan.len() as u32, solution.cost);
+    }
+
+    #[test]
+    fn inverse_actions() {
+        let level = Level::new("+++++\n+   +\n+ A +\n+ 0 +\n+   +\n+++++\n");
+        let state = State::new(&level);
+        for action in Action::all() {
+            if let Some(next) = state.apply(0, action) {
+                assert_eq!(next.apply(0, action.inverse()), Some(state.clone()), "{}", action);
+            }
+        }
+    }
+
+    #[test]
+    fn goal_configurations() {
+        // the box on its goal, the agent on any other cell, even behind the box
+        let level = Level::new("+++++++\n+0 Aa +\n+++++++\n");
+        let problem = LevelProblem::new(&level);
+        let (goals, complete) = goal_states(&problem, MAX_PLACEMENTS);
+        assert_eq!(goals.len(), 4);
+        assert!(complete);
+        assert!(goals.iter().all(|g| g.is_goal_state(&level)));
+
+        let (goals, complete) = goal_states(&problem, 3);
+        assert_eq!(goals.len(), 3);
+        assert!(!complete);
+        assert!(goal_states(&problem, 4).1);
+    }
+
+    #[test]
+    fn reverse_and_bidirectional() {
+        let level = Level::new("++++++++\n+0 A  a+\n+ ++++ +\n+  B  b+\n++++++++\n");
+        let problem = LevelProblem::new(&level);
+        let optimal = bfs(&problem, &Limits::new()).solution.unwrap().cost;
+
+        let solution = reverse(&problem, &Limits::new()).solution.unwrap();
+        check(&problem, &solution);
+        assert_eq!(solution.cost, optimal);
+
+        let solution = bidirectional(&problem, &Limits::new()).solution.unwrap();
+        check(&problem, &solution);
+        assert_eq!(solution.cost, optimal);
+
+        let unsolvable = Level::new("++++++\n+A0 a+\n++++++\n");
+        let outcome = bidirectional(&LevelProblem::new(&unsolvable), &Limits::new());
+        assert!(outcome.solution.is_none());
+        assert_eq!(outcome.stats.termination, Some(Termination::Exhausted));
+    }
+
+    #[test]
+    fn pinned_boxes() {
+        // the spare box that stays put is the one the goal needs, the other one is walled in
+        let level = Level::new("+++++++\n+0A  a+\n+++++++\n+A+++++\n+++++++\n");
+        let problem = LevelProblem::new(&level);
+        assert!(bfs(&problem, &Limits::new()).solution.is_some());
+        assert!(!goal_states(&problem, MAX_PLACEMENTS).1);
+
+        let outcome = reverse(&problem, &Limits::new());
+        assert!(outcome.solution.is_none());
+        assert_eq!(outcome.stats.termination, Some(Termination::Incomplete));
+        let outcome = bidirectional(&problem, &Limits::new());
+        assert_eq!(outcome.stats.termination, Some(Termination::Incomplete));
+    }
+}
@@ -0,0 +1,249 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashSet};
+use std::mem::size_of;
+
+use defs::pos::{Pos, NULL_POS};
+use level::distance::Distances;
+use level::level::Level;
+use state::action::Action;
+use state::joint::JointAction;
+use state::state::State;
+use super::allocation::{Allocation, Tasks};
+use super::agent::{AgentTask, Constraint, LOW_LEVEL_LIMIT};
+use super::graph::Solution;
+use super::stats::{Limits, Monitor, Outcome, Termination};
+
+/// Cost of a set of agent plans.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Objective {
+    SumOfCosts,     // sum of the plan lengths, trailing NoOps excluded
+    Makespan        // length of the longest plan
+}
+
+/// Conflict-Based Search over the agents of a level, each moving its own boxes.
+///
+/// The low level plans one agent at a time, in a world without the other agents and their
+/// boxes, with space-time A* honoring its constraints. The high
+/// level is a best-first search over a constraint tree: the agent plans are run together with
+/// the joint-action executor and the first failing action gives a conflict between two
+/// agents, each child forbidding it for one of them.
+///
+/// Returns the joint plan and its cost for `objective`. Fails when a goal cannot be allocated;
+/// on unsolvable levels the tree is infinite and only the limits stop the search. Nodes left
+/// out, because a low level search hit its limits or a failure was not explained by a conflict,
+/// keep the search from ending as `Exhausted`.
+pub fn cbs(level: &Level, objective: Objective, limits: &Limits) -> Outcome<Solution<JointAction>> {
+    let start = State::new(level);
+    let tables = Distances::all(level);
+    let allocation = Allocation::new(&start, &tables, &Tasks::hungarian(level, &start, &tables));
+    let mut monitor = Monitor::new(limits);
+
+    let mut low_limits = limits.clone().max_expanded(LOW_LEVEL_LIMIT);
+    low_limits.progress = None;
+
+    if allocation.goals.iter().any(|g| g.is_none()) {
+        return monitor.finish(Termination::Exhausted, None);
+    }
+
+    let agents = AgentTask::all(level, &start, &allocation);
+
+    let mut plans = Vec::new();
+    for agent in &agents {
+        match *agent {
+            None => plans.push(Vec::new()),
+            Some(ref agent) => match agent.plan(&[], None, &tables, &low_limits) {
+                Ok(plan) => plans.push(plan),
+                Err(t) => return monitor.finish(t, None)
+            }
+        }
+    }
+
+    let mut nodes = vec!(Node { constraints: Vec::new(), cost: cost(&plans, objective), plans: plans });
+    let mut open = BinaryHeap::new();
+    let mut seen = HashSet::new();
+    let mut left_out = None;
+    let mut memory = nodes[0].mem_size();
+    open.push(Reverse((nodes[0].cost, 0, 0)));
+
+    while let Some(Reverse((_, _, i))) = open.pop() {
+        let open_size = open.len() * size_of::<Reverse<(u32, usize, usize)>>();
+        if let Some(t) = monitor.expand(open.len(), nodes.len(), memory + open_size) { return monitor.finish(t, None); }
+
+        let conflict = match first_conflict(&start, &nodes[i].plans, &allocation) {
+            Ok(None) => {
+                let node = &nodes[i];
+                let solution = Solution { plan: joint_plan(&node.plans, start.nb_agents()), cost: node.cost };
+                return monitor.finish(Termination::Solved, Some(solution));
+            }
+            Ok(Some(conflict)) => conflict,
+            Err(_) => { left_out = Some(Termination::Incomplete); continue; }
+        };
+
+        for &constraint in &[conflict.0, conflict.1] {
+            let a = constraint.agent();
+            let mut constraints = nodes[i].constraints.clone();
+            constraints.push(constraint);
+            constraints.sort_by_key(|c| c.key());
+            if !seen.insert(constraints.clone()) { continue; }
+
+            let own = constraints.iter().filter(|c| c.agent() == a).cloned().collect::<Vec<Constraint>>();
+            let plan = match agents[a].as_ref().map(|agent| agent.plan(&own, None, &tables, &low_limits)) {
+                Some(Ok(plan)) => plan,
+                Some(Err(Termination::Exhausted)) | None => continue,
+                Some(Err(t)) => { left_out = Some(t); continue; }
+            };
+            monitor.generated(1);
+
+            let mut plans = nodes[i].plans.clone();
+            plans[a] = plan;
+            let node = Node { cost: cost(&plans, objective), plans: plans, constraints: constraints };
+            open.push(Reverse((node.cost, node.constraints.len(), nodes.len())));
+            memory += node.mem_size();
+            nodes.push(node);
+        }
+    }
+
+    monitor.finish(left_out.unwrap_or(Termination::Exhausted), None)
+}
+
+struct Node {
+    constraints: Vec<Constraint>,
+    plans: Vec<Vec<Action>>,
+    cost: u32
+}
+
+impl Node {
+    // bytes used by the node, its constraints being kept again in `seen`
+    fn mem_size(&self) -> usize {
+        size_of::<Node>() + 2 * self.constraints.len() * size_of::<Constraint>()
+            + self.plans.iter().map(|p| size_of::<Vec<Action>>() + p.len() * size_of::<Action>()).sum::<usize>()
+    }
+}
+
+fn cost(plans: &[Vec<Action>], objective: Objective) -> u32 {
+    let lengths = plans.iter().map(|p| p.len() as u32);
+    match objective {
+        Objective::SumOfCosts => lengths.sum(),
+        Objective::Makespan   => lengths.max().unwrap_or(0)
+    }
+}
+
+/// Cost of a joint plan for `objective`, the trailing NoOps of each agent excluded.
+pub fn plan_cost(plan: &[JointAction], objective: Objective) -> u32 {
+    let nb_agents = plan.first().map_or(0, |j| j.len());
+    let plans = (0..nb_agents).map(|a| {
+        let mut actions = plan.iter().map(|j| j[a]).collect::<Vec<Action>>();
+        while actions.last() == Some(&Action::NoOp) { actions.pop(); }
+        actions
+    }).collect::<Vec<_>>();
+    cost(&plans, objective)
+}
+
+pub fn joint_plan(plans: &[Vec<Action>], nb_agents: usize) -> Vec<JointAction> {
+    let length = plans.iter().map(|p| p.len()).max().unwrap_or(0);
+    (0..length).map(|t| {
+        JointAction::new((0..nb_agents).map(|a| plans[a].get(t).cloned().unwrap_or(Action::NoOp)).collect())
+    }).collect()
+}
+
+/// Runs the plans together until an action fails, and returns the two constraints resolving
+/// the conflict behind it, one per agent involved. Fails when no other agent is involved.
+fn first_conflict(start: &State, plans: &[Vec<Action>], allocation: &Allocation)
+                  -> Result<Option<(Constraint, Constraint)>, &'static str> {
+    let mut state = start.clone();
+    let mut owners = allocation.boxes.clone();
+
+    for (t, joint) in joint_plan(plans, start.nb_agents()).into_iter().enumerate() {
+        let t = t as u32;
+        let effects = (0..joint.len())
+            .map(|a| if state.agent(a) == NULL_POS { None } else { Some(joint[a].effect(state.agent(a))) })
+            .collect::<Vec<_>>();
+
+        if let Some(i) = joint.outcome(&state).iter().position(|&ok| !ok) {
+            let e = effects[i].unwrap();
+
+            // reached by another applicable action at the same step
+            for (k, l) in joint.conflicts(&state) {
+                if k != i && l != i { continue; }
+                let j = k + l - i;
+                let f = effects[j].unwrap();
+                if let Some(&c) = e.destinations().iter().find(|c| f.destinations().contains(c)) {
+                    return Ok(Some((occupy(i, &e, c, t + 1), occupy(j, &f, c, t + 1))));
+                }
+            }
+
+            for c in e.destinations() {
+                // still held by another agent or one of its boxes
+                let item = state[c];
+                let holder = if item.is_agent() { Some((item.id() as usize, Constraint::Vertex(item.id() as usize, c, t))) }
+                             else if item.is_box() { owners.get(&c).map(|&j| (j, Constraint::BoxCell(j, c, t))) }
+                             else { None };
+                if let Some((j, constraint)) = holder {
+                    if j != i { return Ok(Some((enter(i, &e, c, t), constraint))); }
+                }
+            }
+            return Err("failure without a conflict");
+        }
+
+        for effect in effects.iter().filter_map(|e| e.as_ref()) {
+            if let Some((from, to)) = effect.box_move {
+                if let Some(owner) = owners.remove(&from) { owners.insert(to, owner); }
+            }
+        }
+        joint.execute(&mut state);
+    }
+
+    Ok(None)
+}
+
+// the object of `agent` that reaches `cell` is not there at `time`
+fn occupy(agent: usize, effect: &::state::action::Effect, cell: Pos, time: u32) -> Constraint {
+    if effect.agent_to == cell { Constraint::Vertex(agent, cell, time) } else { Constraint::BoxCell(agent, cell, time) }
+}
+
+// the object of `agent` does not enter `cell` at `step`
+fn enter(agent: usize, effect: &::state::action::Effect, cell: Pos, step: u32) -> Constraint {
+    if effect.agent_to == cell { Constraint::Edge(agent, effect.agent_from, cell, step) }
+    else { Constraint::BoxCell(agent, cell, step + 1) }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn check(level: &Level, solution: &Solution<JointAction>) {
+        let mut state = State::new(level);
+        for joint in &solution.plan {
+            assert!(joint.execute(&mut state).iter().all(|&ok| ok), "{}", joint);
+        }
+        assert!(state.is_goal_state(level));
+    }
+
+    #[test]
+    fn crossing_boxes() {
+        let level = Level::new("red: 0, A\nblue: 1, B\n+++++++++\n+0A   B1+\n+  b a  +\n+       +\n+++++++++\n");
+
+        for &objective in &[Objective::SumOfCosts, Objective::Makespan] {
+            let outcome = cbs(&level, objective, &Limits::new().max_expanded(1000));
+            let solution = outcome.solution.expect("no plan");
+            check(&level, &solution);
+            assert_eq!(plan_cost(&solution.plan, objective), solution.cost);
+            if objective == Objective::Makespan { assert_eq!(solution.cost as usize, solution.plan.len()); }
+        }
+
+        let outcome = cbs(&level, Objective::SumOfCosts, &Limits::new().max_memory(1));
+        assert_eq!(outcome.stats.termination, Some(Termination::MemoryLimit));
+    }
+
+    #[test]
+    fn agents_in_the_way() {
+        // agent 1 stands in the corridor agent 0 needs, and has nothing to do
+        let level = Level::new("red: 0, A\nblue: 1\n+++++++\n+0A 1a+\n+++++++\n");
+        let outcome = cbs(&level, Objective::SumOfCosts, &Limits::new().max_expanded(50));
+        assert!(outcome.solution.is_none());
+
+        let level = Level::new("red: 0, A\nblue: 1\n+++++++\n+0A 1a+\n++++ ++\n+++++++\n");
+        let solution = cbs(&level, Objective::SumOfCosts, &Limits::new()).solution.expect("no plan");
+        check(&level, &solution);
+    }
+}
@@ -0,0 +1,417 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use defs::dir::{DIRS, EAST, SOUTH};
+use defs::grid::Grid;
+use defs::pos::{Pos, NULL_POS};
+use level::component::Component;
+use level::dead::DeadSquares;
+use level::goal::{Goal, Target};
+use level::item::Color;
+use level::level::Level;
+use state::action::Action;
+use state::state::State;
+use state::zobrist;
+use super::graph::bfs;
+use super::patterns::{self, PatternDb};
+use super::problem::Problem;
+use super::stats::{Limits, Termination};
+
+/// Default number of expansions allowed to prove a corral deadlock.
+pub const CORRAL_LIMIT: u64 = 200;
+
+/// Number of corral results cached, the cache is emptied when full.
+pub const CORRAL_CACHE_SIZE: usize = 1 << 16;
+
+/// Dynamic deadlock detection, on top of the static dead squares:
+///  - freeze deadlocks: a box that can move along neither axis, because walls, immovable
+///    boxes or other frozen boxes block it, while it is not on one of its goals. Boxes with no
+///    agent of their color are immovable;
+///  - corral deadlocks: an area the agents cannot enter, enclosed by boxes, which cannot be
+///    opened nor solved. This is proved by a bounded search where every box outside of the
+///    corral, and every agent whose color is none of the corral's boxes' colors, is removed,
+///    which can only make the problem easier. Results are cached by corral, boundary boxes
+///    and agents left;
+///  - patterns: windows around a moved box found in an offline `PatternDb`, when the window
+///    holds no box goal and no spare box.
+///
+/// Box kinds with more boxes than goals are never reported, a spare box may stay anywhere.
+pub struct Deadlocks<'a> {
+    level: &'a Level,
+    tables: Arc<DeadlockTables>,
+    corral_limit: u64,
+    patterns: Option<&'a PatternDb>,
+    corral_cache: RefCell<HashMap<u64, bool>>
+}
+
+/// What deadlock detection knows of a level before searching it, shared by the searches of
+/// the level, e.g. between threads.
+pub struct DeadlockTables {
+    dead: DeadSquares,
+    goals: Grid<Option<Goal>>,
+    comps: Vec<Component>,
+    agent_colors: Vec<Color>,
+    spare: HashSet<(u8, Color)>
+}
+
+impl DeadlockTables {
+    pub fn new(level: &Level) -> DeadlockTables {
+        let (rows, cols) = level.size();
+        let mut goals = Grid::<Option<Goal>>::new(rows, cols);
+        let mut agent_colors = Vec::new();
+        let mut nb_boxes = Vec::<((u8, Color), isize)>::new();
+
+        for goal in level.goals() {
+            goals[goal.pos] = Some(*goal);
+            if let Target::Box(letter) = goal.target { count(&mut nb_boxes, (letter, goal.color), -1); }
+        }
+
+        for row in 0..rows {
+            for col in 0..cols {
+                let item = level[Pos::new(row as i8, col as i8)];
+                if item.is_agent() { agent_colors.push(item.color()); }
+                if item.is_box() { count(&mut nb_boxes, (item.id(), item.color()), 1); }
+            }
+        }
+
+        DeadlockTables {
+            dead: DeadSquares::new(level),
+            goals: goals,
+            comps: Component::all(level),
+            agent_colors: agent_colors,
+            spare: nb_boxes.into_iter().filter(|&(_, n)| n > 0).map(|(k, _)| k).collect()
+        }
+    }
+}
+
+impl<'a> Deadlocks<'a> {
+    pub fn new(level: &'a Level) -> Deadlocks<'a> {
+        Self::with_tables(level, Arc::new(DeadlockTables::new(level)))
+    }
+
+    /// Detection with tables built beforehand for `level`; the corral cache is its own.
+    pub fn with_tables(level: &'a Level, tables: Arc<DeadlockTables>) -> Deadlocks<'a> {
+        Deadlocks {
+            level: level,
+            tables: tables,
+            corral_limit: CORRAL_LIMIT,
+            patterns: None,
+            corral_cache: RefCell::new(HashMap::new())
+        }
+    }
+
+    pub fn dead_squares(&self) -> &DeadSquares { &self.tables.dead }
+
+    /// Number of expansions allowed to each corral search, 0 disables corral detection.
+    pub fn set_corral_limit(&mut self, limit: u64) { self.corral_limit = limit; }
+
+    pub fn set_patterns(&mut self, db: &'a PatternDb) { self.patterns = Some(db); }
+
+    /// Incremental check after a box was moved to `pos`: freeze of this box and its neighbors,
+    /// and corrals touching it.
+    pub fn after_move(&self, state: &State, pos: Pos) -> bool {
+        if self.frozen_off_goal(state, pos) { return true; }
+        if DIRS.iter().any(|&d| state.in_bounds(pos + d) && state[pos + d].is_box() && self.frozen_off_goal(state, pos + d)) {
+            return true;
+        }
+        if self.matches_pattern(state, pos) { return true; }
+
+        let corrals = self.corrals(state);
+        corrals.iter()
+            .filter(|c| c.iter().any(|&p| DIRS.iter().any(|&d| p + d == pos)))
+            .any(|c| self.is_corral_deadlock(state, c))
+    }
+
+    /// Full check of a state.
+    pub fn is_deadlocked(&self, state: &State) -> bool {
+        let (rows, cols) = state.size();
+        for row in 0..rows {
+            for col in 0..cols {
+                let pos = Pos::new(row as i8, col as i8);
+                if state[pos].is_box() && (self.frozen_off_goal(state, pos) || self.matches_pattern(state, pos)) {
+                    return true;
+                }
+            }
+        }
+
+        self.corrals(state).iter().any(|c| self.is_corral_deadlock(state, c))
+    }
+
+    /// Whether a window of the pattern database containing `pos` matches the state.
+    fn matches_pattern(&self, state: &State, pos: Pos) -> bool {
+        let db = match self.patterns { Some(db) => db, None => return false };
+
+        for (rows, cols) in db.shapes() {
+            for r in 0..rows {
+                for c in 0..cols {
+                    let corner = pos - Pos::new(r as i8, c as i8);
+                    let code = match patterns::encode(state, corner, rows, cols) { Some(code) => code, None => continue };
+                    if db.contains(rows, cols, code) && self.is_relevant(state, corner, rows, cols) { return true; }
+                }
+            }
+        }
+        false
+    }
+
+    // no box goal in the window, where a box could stay, and no spare box
+    fn is_relevant(&self, state: &State, corner: Pos, rows: usize, cols: usize) -> bool {
+        for r in 0..rows {
+            for c in 0..cols {
+                let pos = corner + Pos::new(r as i8, c as i8);
+                if self.tables.goals[pos].is_some_and(|g| g.is_box_goal()) { return false; }
+                let item = state[pos];
+                if item.is_box() && self.tables.spare.contains(&(item.id(), item.color())) { return false; }
+            }
+        }
+        true
+    }
+
+    fn on_goal(&self, state: &State, pos: Pos) -> bool {
+        self.tables.goals[pos].is_some_and(|g| g.is_satisfied_by(state[pos]))
+    }
+
+    fn frozen_off_goal(&self, state: &State, pos: Pos) -> bool {
+        let item = state[pos];
+        if self.on_goal(state, pos) || self.tables.spare.contains(&(item.id(), item.color())) { return false; }
+        self.is_frozen(state, pos)
+    }
+
+    /// Whether the box on `pos` can never move again.
+    pub fn is_frozen(&self, state: &State, pos: Pos) -> bool {
+        let mut movable = HashSet::new();
+        self.frozen(state, pos, &mut Vec::new(), &mut movable)
+    }
+
+    // `visiting` holds the boxes being checked, assumed to be walls by the boxes they depend on.
+    // Only "movable" results are cached: adding walls never makes a frozen box movable.
+    fn frozen(&self, state: &State, pos: Pos, visiting: &mut Vec<Pos>, movable: &mut HashSet<Pos>) -> bool {
+        if !self.tables.agent_colors.contains(&state[pos].color()) { return true; }
+        if movable.contains(&pos) { return false; }
+
+        visiting.push(pos);
+        let frozen = self.axis_blocked(state, pos, EAST, visiting, movable)
+            && self.axis_blocked(state, pos, SOUTH, visiting, movable);
+        visiting.pop();
+
+        if !frozen { movable.insert(pos); }
+        frozen
+    }
+
+    /// A box moves to `pos + d` pushed by an agent entering `pos` from any side but `pos + d`,
+    /// or pulled by an agent leaving `pos + d` to any side but `pos`: moves can turn.
+    fn axis_blocked(&self, state: &State, pos: Pos, dir: Pos, visiting: &mut Vec<Pos>, movable: &mut HashSet<Pos>) -> bool {
+        let item = state[pos];
+
+        for &d in &[dir, -dir] {
+            let to = pos + d;
+            if self.blocking(state, to, visiting, movable) || self.tables.dead.is_dead(item, to) { continue; }
+
+            let sides = DIRS.iter().cloned().filter(|&a| a != -d).collect::<Vec<Pos>>();
+            let pushed = sides.iter().any(|&a| !self.blocking(state, pos - a, visiting, movable));
+            let pulled = sides.iter().any(|&a| !self.blocking(state, to + a, visiting, movable));
+            if pushed || pulled { return false; }
+        }
+
+        true
+    }
+
+    fn blocking(&self, state: &State, pos: Pos, visiting: &mut Vec<Pos>, movable: &mut HashSet<Pos>) -> bool {
+        if !state.in_bounds(pos) { return true; }
+        let item = state[pos];
+        item.is_wall() || (item.is_box() && (visiting.contains(&pos) || self.frozen(state, pos, visiting, movable)))
+    }
+
+    /// Areas of free cells that no agent can reach.
+    fn corrals(&self, state: &State) -> Vec<Vec<Pos>> {
+        let reached = reachable(state);
+        let (rows, cols) = state.size();
+        let mut done = Grid::<bool>::new(rows, cols);
+        let mut corrals = Vec::new();
+
+        for comp in &self.tables.comps {
+            for i in 0..comp.nb_free_cells() {
+                let start = comp.pos_of(i as i16);
+                if done[start] || reached[start] || !state.is_free(start) { continue; }
+
+                let mut corral = Vec::new();
+                let mut stack = vec!(start);
+                done[start] = true;
+
+                while let Some(p) = stack.pop() {
+                    corral.push(p);
+                    for &d in &DIRS {
+                        let q = p + d;
+                        if state.is_free(q) && !done[q] { done[q] = true; stack.push(q); }
+                    }
+                }
+                corrals.push(corral);
+            }
+        }
+
+        corrals
+    }
+
+    fn is_corral_deadlock(&self, state: &State, corral: &[Pos]) -> bool {
+        if self.corral_limit == 0 { return false; }
+
+        let mut boundary = Vec::new();
+        for &p in corral {
+            for &d in &DIRS {
+                let q = p + d;
+                if state.in_bounds(q) && state[q].is_box() && !boundary.contains(&q) { boundary.push(q); }
+            }
+        }
+
+        let needs_work = corral.iter().any(|&p| self.tables.goals[p].is_some())
+            || boundary.iter().any(|&b| !self.on_goal(state, b) && !self.tables.spare.contains(&(state[b].id(), state[b].color())));
+        if !needs_work { return false; }
+
+        let colors = boundary.iter().map(|&b| state[b].color()).collect::<Vec<Color>>();
+        let mut relaxed = state.clone();
+        let (rows, cols) = state.size();
+        for row in 0..rows {
+            for col in 0..cols {
+                let pos = Pos::new(row as i8, col as i8);
+                let item = relaxed[pos];
+                if (item.is_box() && !boundary.contains(&pos)) || (item.is_agent() && !colors.contains(&item.color())) {
+                    relaxed.remove(pos);
+                }
+            }
+        }
+
+        // the relaxed problem is given by the corral, its boundary boxes and the agents left
+        let mut key = corral.iter().fold(0, |h, &p| h ^ zobrist::splitmix64(p.row as u64 * 256 + p.col as u8 as u64));
+        for &b in &boundary { key ^= zobrist::key(b, state[b]); }
+        for &a in relaxed.agents().iter().filter(|&&a| a != NULL_POS) { key ^= zobrist::key(a, relaxed[a]); }
+        if let Some(&deadlock) = self.corral_cache.borrow().get(&key) { return deadlock; }
+
+        let needed = boundary.iter().filter(|&&b| !self.tables.spare.contains(&(state[b].id(), state[b].color()))).count();
+        let problem = CorralProblem { deadlocks: self, start: relaxed, corral: corral, needed: needed };
+        let outcome = bfs(&problem, &Limits::new().max_expanded(self.corral_limit));
+        let deadlock = outcome.stats.termination == Some(Termination::Exhausted);
+
+        let mut cache = self.corral_cache.borrow_mut();
+        if cache.len() >= CORRAL_CACHE_SIZE { cache.clear(); }
+        cache.insert(key, deadlock);
+        deadlock
+    }
+}
+
+fn count(counts: &mut Vec<((u8, Color), isize)>, kind: (u8, Color), n: isize) {
+    match counts.iter().position(|&(k, _)| k == kind) {
+        Some(i) => counts[i].1 += n,
+        None => counts.push((kind, n))
+    }
+}
+
+/// Cells reachable by some agent without moving any box.
+pub fn reachable(state: &State) -> Grid<bool> {
+    let (rows, cols) = state.size();
+    let mut reached = Grid::<bool>::new(rows, cols);
+    let mut stack = state.agents().iter().cloned().filter(|&a| a != NULL_POS).collect::<Vec<Pos>>();
+
+    for &a in &stack { reached[a] = true; }
+    while let Some(p) = stack.pop() {
+        for &d in &DIRS {
+            let q = p + d;
+            if state.is_free(q) && !reached[q] { reached[q] = true; stack.push(q); }
+        }
+    }
+
+    reached
+}
+
+/// Can the corral be opened (an agent reaches one of its cells), or its goals and boxes be
+/// solved, with only the corral's boxes left?
+struct CorralProblem<'d, 'a: 'd, 'c> {
+    deadlocks: &'d Deadlocks<'a>,
+    start: State,
+    corral: &'c [Pos],
+    needed: usize           // boxes of the corral that are not spare, they must all end on goals
+}
+
+impl<'d, 'a, 'c> Problem for CorralProblem<'d, 'a, 'c> {
+    type Node = State;
+    type Action = (usize, Action);
+    type Key = State;
+
+    fn initial(&self) -> Vec<State> { vec!(self.start.clone()) }
+
+    fn is_goal(&self, state: &State) -> bool {
+        let reached = reachable(state);
+        if self.corral.iter().any(|&p| reached[p]) { return true; }
+
+        let corral_solved = self.corral.iter()
+            .filter_map(|&p| self.deadlocks.tables.goals[p])
+            .all(|g| state.satisfies(&g));
+        let on_goals = self.deadlocks.level.goals().iter()
+            .filter(|g| g.is_box_goal() && state.satisfies(g))
+            .count();
+
+        corral_solved && on_goals >= self.needed
+    }
+
+    fn expand(&self, state: &State, out: &mut Vec<((usize, Action), State, u32)>) {
+        for agent in 0..state.nb_agents() {
+            for action in Action::all() {
+                if action == Action::NoOp { continue; }
+                if let Some(next) = state.apply(agent, action) { out.push(((agent, action), next, 1)); }
+            }
+        }
+    }
+
+    fn key(&self, state: &State) -> State { state.clone() }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use search::problem::LevelProblem;
+
+    #[test]
+    fn freeze() {
+        // two boxes walled in a pocket: neither can move along any axis
+        let level = Level::new("+++++++\n+AA+0a+\n+++++a+\n+++++++\n");
+        let deadlocks = Deadlocks::new(&level);
+        let state = State::new(&level);
+
+        assert!(deadlocks.is_frozen(&state, Pos::new(1, 1)));
+        assert!(deadlocks.is_frozen(&state, Pos::new(1, 2)));
+        assert!(deadlocks.is_deadlocked(&state));
+        assert!(LevelProblem::new(&level).initial().is_empty());
+
+        // against a dead end, the outer box can still be pulled out along the corridor
+        let level = Level::new("++++++\n+AA ++\n+++ ++\n+a 0a+\n++++++\n");
+        let deadlocks = Deadlocks::new(&level);
+        let state = State::new(&level);
+        assert!(!deadlocks.is_frozen(&state, Pos::new(1, 2)));
+        assert!(!deadlocks.is_deadlocked(&state));
+    }
+
+    #[test]
+    fn immovable_color() {
+        // nobody can move the red box, which is not on its goal
+        let level = Level::new("red: B\n+++++++\n+0  B +\n+  b  +\n+++++++\n");
+        let deadlocks = Deadlocks::new(&level);
+        let state = State::new(&level);
+        assert!(deadlocks.is_frozen(&state, Pos::new(1, 4)));
+        assert!(deadlocks.after_move(&state, Pos::new(1, 4)));
+    }
+
+    #[test]
+    fn corral() {
+        // B can only shuttle between the corridor and the goal cell behind it, so A never
+        // reaches its goal, although B itself is not frozen
+        let level = Level::new("+++++\n+ Ba+\n+ +++\n+0A +\n+++++\n");
+        let mut deadlocks = Deadlocks::new(&level);
+        let state = State::new(&level);
+
+        assert!(!deadlocks.is_frozen(&state, Pos::new(1, 2)));
+        assert!(deadlocks.is_deadlocked(&state));
+        assert!(deadlocks.after_move(&state, Pos::new(1, 2)));
+
+        deadlocks.set_corral_limit(0);
+        assert!(!deadlocks.is_deadlocked(&state));
+    }
+}
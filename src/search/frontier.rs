@@ -0,0 +1,112 @@
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, VecDeque};
+
+/// An open node waiting for expansion.
+#[derive(Debug, Clone, Copy)]
+pub struct Entry {
+    pub f: f64,             // evaluation, lower first
+    pub h: u32,             // tie breaker, lower first
+    pub g: u32,             // cost of the node when it was pushed
+    pub index: usize        // index of the node record in the search
+}
+
+pub trait Frontier {
+    fn push(&mut self, entry: Entry);
+    fn pop(&mut self) -> Option<Entry>;
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool { self.len() == 0 }
+}
+
+/// First in, first out: breadth-first search.
+#[derive(Default)]
+pub struct Queue(VecDeque<Entry>);
+
+/// Last in, first out: depth-first search.
+#[derive(Default)]
+pub struct Stack(Vec<Entry>);
+
+/// Lowest `f` first, then lowest `h`, then oldest.
+#[derive(Default)]
+pub struct Priority {
+    heap: BinaryHeap<Ranked>,
+    seq: u64
+}
+
+impl Queue    { pub fn new() -> Queue { Queue(VecDeque::new()) } }
+impl Stack    { pub fn new() -> Stack { Stack(Vec::new()) } }
+impl Priority { pub fn new() -> Priority { Priority { heap: BinaryHeap::new(), seq: 0 } } }
+
+impl Frontier for Queue {
+    fn push(&mut self, entry: Entry) { self.0.push_back(entry); }
+    fn pop(&mut self) -> Option<Entry> { self.0.pop_front() }
+    fn len(&self) -> usize { self.0.len() }
+}
+
+impl Frontier for Stack {
+    fn push(&mut self, entry: Entry) { self.0.push(entry); }
+    fn pop(&mut self) -> Option<Entry> { self.0.pop() }
+    fn len(&self) -> usize { self.0.len() }
+}
+
+impl Frontier for Priority {
+    fn push(&mut self, entry: Entry) {
+        self.seq += 1;
+        self.heap.push(Ranked { entry: entry, seq: self.seq });
+    }
+
+    fn pop(&mut self) -> Option<Entry> { self.heap.pop().map(|r| r.entry) }
+    fn len(&self) -> usize { self.heap.len() }
+}
+
+struct Ranked {
+    entry: Entry,
+    seq: u64
+}
+
+impl Ord for Ranked {
+    // BinaryHeap is a max-heap, so every comparison is reversed
+    fn cmp(&self, other: &Ranked) -> Ordering {
+        other.entry.f.partial_cmp(&self.entry.f).unwrap_or(Ordering::Equal)
+            .then(other.entry.h.cmp(&self.entry.h))
+            .then(other.seq.cmp(&self.seq))
+    }
+}
+
+impl PartialOrd for Ranked {
+    fn partial_cmp(&self, other: &Ranked) -> Option<Ordering> { Some(self.cmp(other)) }
+}
+
+impl PartialEq for Ranked {
+    fn eq(&self, other: &Ranked) -> bool { self.cmp(other) == Ordering::Equal }
+}
+
+impl Eq for Ranked {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn entry(f: f64, h: u32, index: usize) -> Entry { Entry { f: f, h: h, g: 0, index: index } }
+
+    #[test]
+    fn priority_order() {
+        let mut p = Priority::new();
+        p.push(entry(3.0, 0, 0));
+        p.push(entry(1.0, 2, 1));
+        p.push(entry(1.0, 1, 2));
+        p.push(entry(1.0, 1, 3));
+
+        let order = (0..4).map(|_| p.pop().unwrap().index).collect::<Vec<usize>>();
+        assert_eq!(order, vec!(2, 3, 1, 0));
+        assert!(p.is_empty());
+    }
+
+    #[test]
+    fn queue_stack_order() {
+        let (mut q, mut s) = (Queue::new(), Stack::new());
+        for i in 0..3 { q.push(entry(0.0, 0, i)); s.push(entry(0.0, 0, i)); }
+        assert_eq!(q.pop().unwrap().index, 0);
+        assert_eq!(s.pop().unwrap().index, 2);
+    }
+}
@@ -0,0 +1,221 @@
+use std::collections::HashMap;
+use std::mem::size_of;
+
+use super::frontier::{Entry, Frontier, Priority, Queue, Stack};
+use super::heuristic::{Heuristic, Zero};
+use super::problem::Problem;
+use super::stats::{Limits, Monitor, Outcome, Termination};
+
+/// A plan reaching a goal node, with its cost.
+#[derive(Debug, Clone)]
+pub struct Solution<A> {
+    pub plan: Vec<A>,
+    pub cost: u32
+}
+
+/// Evaluation function f = g_weight·g + h_weight·h used to rank open nodes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Weights {
+    pub g: f64,
+    pub h: f64
+}
+
+struct Record<N, A> {
+    node: N,
+    parent: Option<usize>,
+    action: Option<A>,
+    g: u32,
+    h: u32,
+    expanded: bool
+}
+
+/// Graph search with duplicate detection: a node generated again is only updated if it has
+/// not been expanded yet and is reached with a lower cost. Nodes are tested for goal when
+/// they are expanded, so A* with a consistent heuristic returns an optimal plan.
+///
+/// With `limits.max_cost`, nodes whose g + h reaches the bound are pruned, so only plans
+/// cheaper than the bound are found (assuming an admissible heuristic).
+pub fn graph_search<P, F, H>(problem: &P, mut frontier: F, heuristic: &H, weights: Weights, limits: &Limits)
+    -> Outcome<Solution<P::Action>> where P: Problem, F: Frontier, H: Heuristic<P::Node>
+{
+    let mut monitor = Monitor::new(limits);
+    let mut records = Vec::<Record<P::Node, P::Action>>::new();
+    let mut seen = HashMap::<P::Key, usize>::new();
+    let mut children = Vec::new();
+    let mut node_size = 0;
+    let max_cost = limits.max_cost.unwrap_or(u32::MAX);
+
+    for node in problem.initial() {
+        // the node is stored in its record, and its key in `seen`
+        let key = problem.key(&node);
+        node_size = problem.node_size(&node) + problem.key_size(&key)
+            + size_of::<Record<P::Node, P::Action>>() + size_of::<usize>();
+        if seen.contains_key(&key) { continue; }
+        let h = heuristic.estimate(&node);
+        if h >= max_cost { continue; }
+        seen.insert(key, records.len());
+        frontier.push(Entry { f: weights.h * h as f64, h: h, g: 0, index: records.len() });
+        records.push(Record { node: node, parent: None, action: None, g: 0, h: h, expanded: false });
+    }
+
+    while let Some(entry) = frontier.pop() {
+        let i = entry.index;
+        if records[i].expanded || records[i].g != entry.g { continue; }  // stale entry
+        records[i].expanded = true;
+
+        if problem.is_goal(&records[i].node) {
+            return monitor.finish(Termination::Solved, Some(extract(&records, i)));
+        }
+
+        let memory = records.len() * node_size + frontier.len() * size_of::<Entry>();
+        if let Some(t) = monitor.expand(frontier.len(), records.len(), memory) {
+            return monitor.finish(t, None);
+        }
+
+        problem.expand(&records[i].node, &mut children);
+        monitor.generated(children.len());
+
+        for (action, child, cost) in children.drain(..) {
+            let g = entry.g + cost;
+
+            let key = problem.key(&child);
+            let j = if let Some(&j) = seen.get(&key) {
+                if records[j].expanded || records[j].g <= g { continue; }
+                records[j].parent = Some(i);
+                records[j].action = Some(action);
+                records[j].g = g;
+                j
+            } else {
+                let h = heuristic.estimate(&child);
+                seen.insert(key, records.len());
+                records.push(Record { node: child, parent: Some(i), action: Some(action), g: g, h: h, expanded: false });
+                records.len() - 1
+            };
+
+            let h = records[j].h;
+            if g + h >= max_cost { continue; }
+            frontier.push(Entry { f: weights.g * g as f64 + weights.h * h as f64, h: h, g: g, index: j });
+        }
+    }
+
+    monitor.finish(Termination::Exhausted, None)
+}
+
+fn extract<N, A: Clone>(records: &[Record<N, A>], goal: usize) -> Solution<A> {
+    let mut plan = Vec::new();
+    let mut i = goal;
+
+    while let Some(parent) = records[i].parent {
+        plan.push(records[i].action.clone().unwrap());
+        i = parent;
+    }
+
+    plan.reverse();
+    Solution { plan: plan, cost: records[goal].g }
+}
+
+pub fn bfs<P: Problem>(problem: &P, limits: &Limits) -> Outcome<Solution<P::Action>> {
+    graph_search(problem, Queue::new(), &Zero, Weights { g: 0.0, h: 0.0 }, limits)
+}
+
+pub fn dfs<P: Problem>(problem: &P, limits: &Limits) -> Outcome<Solution<P::Action>> {
+    graph_search(problem, Stack::new(), &Zero, Weights { g: 0.0, h: 0.0 }, limits)
+}
+
+pub fn astar<P, H>(problem: &P, heuristic: &H, limits: &Limits) -> Outcome<Solution<P::Action>>
+    where P: Problem, H: Heuristic<P::Node>
+{
+    weighted_astar(problem, heuristic, 1.0, limits)
+}
+
+pub fn weighted_astar<P, H>(problem: &P, heuristic: &H, weight: f64, limits: &Limits) -> Outcome<Solution<P::Action>>
+    where P: Problem, H: Heuristic<P::Node>
+{
+    graph_search(problem, Priority::new(), heuristic, Weights { g: 1.0, h: weight }, limits)
+}
+
+pub fn greedy<P, H>(problem: &P, heuristic: &H, limits: &Limits) -> Outcome<Solution<P::Action>>
+    where P: Problem, H: Heuristic<P::Node>
+{
+    graph_search(problem, Priority::new(), heuristic, Weights { g: 0.0, h: 1.0 }, limits)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use level::level::Level;
+    use search::heuristic::GoalCount;
+    use search::problem::{LevelProblem, Step};
+    use search::stats::{Limits, Termination};
+
+    fn check(problem: &LevelProblem, solution: &Solution<Step>) {
+        let mut state = problem.start().clone();
+        for &(agent, action) in &solution.plan {
+            state = state.apply(agent, action).expect("invalid plan");
+        }
+        assert!(state.is_goal_state(problem.level()));
+        assert_eq!(solution.plan.len() as u32, solution.cost);
+    }
+
+    #[test]
+    fn strategies() {
+        let level = Level::from_file("levels/easy.lvl").unwrap();
+        let problem = LevelProblem::new(&level);
+        let h = GoalCount::new(&level);
+        let l = Limits::new();
+
+        let optimal = bfs(&problem, &l).solution.unwrap();
+        check(&problem, &optimal);
+
+        let a = astar(&problem, &h, &l);
+        check(&problem, a.solution.as_ref().unwrap());
+        assert_eq!(a.solution.unwrap().cost, optimal.cost);
+        assert_eq!(a.stats.termination, Some(Termination::Solved));
+
+        let w = weighted_astar(&problem, &h, 3.0, &l).solution.unwrap();
+        check(&problem, &w);
+        assert!(w.cost as f64 <= 3.0 * optimal.cost as f64);
+
+        check(&problem, &dfs(&problem, &l).solution.unwrap());
+        check(&problem, &greedy(&problem, &h, &l).solution.unwrap());
+    }
+
+    #[test]
+    fn sideways_moves() {
+        // the box only gets out turning: Pull(E,N), Push(W,S), Push(S,W)
+        let level = Level::new("+++++\n+  ++\n+ A++\n++0 +\n+a ++\n+++++\n");
+        let problem = LevelProblem::new(&level);
+        let solution = bfs(&problem, &Limits::new()).solution.expect("pruned away");
+        check(&problem, &solution);
+        assert_eq!(solution.cost, 3);
+    }
+
+    #[test]
+    fn limits() {
+        let level = Level::from_file("levels/easy.lvl").unwrap();
+        let problem = LevelProblem::new(&level);
+
+        let outcome = bfs(&problem, &Limits::new().max_expanded(5));
+        assert!(outcome.solution.is_none());
+        assert_eq!(outcome.stats.termination, Some(Termination::ExpansionLimit));
+        assert_eq!(outcome.stats.expanded, 5);
+
+        let outcome = bfs(&problem, &Limits::new().max_memory(1));
+        assert_eq!(outcome.stats.termination, Some(Termination::MemoryLimit));
+
+        // only plans cheaper than the bound are found
+        let optimal = bfs(&problem, &Limits::new()).solution.unwrap().cost;
+        assert_eq!(bfs(&problem, &Limits::new().max_cost(optimal)).stats.termination, Some(Termination::Exhausted));
+        assert_eq!(bfs(&problem, &Limits::new().max_cost(optimal + 1)).solution.unwrap().cost, optimal);
+    }
+
+    #[test]
+    fn unsolvable() {
+        let level = Level::new("++++++\n+A0 a+\n++++++\n");
+        let problem = LevelProblem::new(&level);
+        let outcome = bfs(&problem, &Limits::new());
+        assert!(outcome.solution.is_none());
+        assert_eq!(outcome.stats.termination, Some(Termination::Exhausted));
+        assert!(outcome.stats.expanded > 0 && outcome.stats.generated >= outcome.stats.expanded);
+    }
+}
@@ -0,0 +1,325 @@
+use std::collections::HashMap;
+use std::mem::size_of;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use level::level::Level;
+use state::state::State;
+use super::deadlock::DeadlockTables;
+use super::frontier::{Entry, Frontier, Priority};
+use super::graph::Solution;
+use super::heuristic::Heuristic;
+use super::matching::Matching;
+use super::patterns::PatternDb;
+use super::problem::{LevelProblem, Problem, Step};
+use super::stats::{Limits, Monitor, Outcome, SearchStats, Termination};
+
+// a node sent to its owner: state, cost, and the record it was generated from
+type Message = (State, u32, Option<(Link, Step)>);
+
+// thread and record index of a node
+type Link = (usize, usize);
+
+struct Record {
+    node: State,
+    parent: Option<(Link, Step)>,
+    g: u32,
+    h: u32
+}
+
+/// What the threads share.
+struct Shared {
+    senders: Vec<Sender<Message>>,
+    work: AtomicUsize,              // active threads plus messages sent and not yet handled
+    incumbent: AtomicUsize,         // cost of the best plan found so far
+    stop: AtomicBool,
+    limit: Mutex<Option<Termination>>
+}
+
+/// What a thread leaves behind: parent links and costs of its records, its best goal and stats.
+struct Part {
+    links: Vec<(Option<(Link, Step)>, u32)>,
+    goal: Option<usize>,
+    stats: SearchStats
+}
+
+/// Hash distributed A*: every state is owned by one of `threads` threads, chosen by its
+/// Zobrist hash. Each thread expands its own open list in f order and sends the successors to
+/// their owners over channels. A node reached again with a lower cost is reopened.
+///
+/// Goals found update a shared incumbent that prunes every node with f ≥ its cost. The search
+/// ends when no thread has an open node under the incumbent and no message is in flight: the
+/// `work` counter is held by each busy thread and each message in flight, and can only rise
+/// from a busy thread or a message, so it stays at 0 once there. With an admissible heuristic
+/// the plan is optimal.
+///
+/// `max_expanded` and `max_memory` are split evenly between the threads. The heuristic and the
+/// deadlock tables are built once and shared, each thread keeping its own corral cache.
+pub fn hdastar(level: Arc<Level>, patterns: Option<Arc<PatternDb>>, threads: usize, limits: &Limits)
+    -> Outcome<Solution<Step>>
+{
+    let threads = threads.max(1);
+    let start = Instant::now();
+    let heuristic = Arc::new(Matching::new(&level));
+    let tables = Arc::new(DeadlockTables::new(&level));
+    let (senders, receivers): (Vec<_>, Vec<_>) = (0..threads).map(|_| channel()).unzip();
+
+    // no root at all when the start is already deadlocked
+    let roots = {
+        let mut problem = LevelProblem::with_tables(&level, tables.clone());
+        if let Some(ref db) = patterns { problem.set_patterns(db); }
+        problem.initial()
+    };
+
+    let shared = Arc::new(Shared {
+        senders: senders,
+        work: AtomicUsize::new(threads + roots.len()),
+        incumbent: AtomicUsize::new(u32::MAX as usize),
+        stop: AtomicBool::new(false),
+        limit: Mutex::new(None)
+    });
+
+    let mut part_limits = limits.clone();
+    part_limits.max_expanded = limits.max_expanded.map(|n| n / threads as u64 + 1);
+    part_limits.max_memory = limits.max_memory.map(|m| m / threads);
+
+    for root in roots {
+        let owner = root.hash() as usize % threads;
+        shared.senders[owner].send((root, 0, None)).unwrap();
+    }
+
+    let handles = receivers.into_iter().enumerate().map(|(me, receiver)| {
+        let (level, patterns, heuristic, shared) = (level.clone(), patterns.clone(), heuristic.clone(), shared.clone());
+        let tables = tables.clone();
+        let mut limits = part_limits.clone();
+        if me != 0 { limits.progress = None; }
+
+        thread::spawn(move || {
+            let mut problem = LevelProblem::with_tables(&level, tables);
+            if let Some(ref db) = patterns { problem.set_patterns(db); }
+            Worker::new(me, &problem, &*heuristic, &shared, &limits).run(receiver)
+        })
+    }).collect::<Vec<_>>();
+
+    let parts = handles.into_iter().map(|h| h.join().expect("search thread panicked")).collect::<Vec<Part>>();
+
+    let mut stats = SearchStats {
+        expanded: 0, generated: 0, frontier_peak: 0, closed: 0, memory: 0,
+        elapsed: start.elapsed(), termination: None
+    };
+    for part in &parts {
+        stats.expanded += part.stats.expanded;
+        stats.generated += part.stats.generated;
+        stats.frontier_peak += part.stats.frontier_peak;
+        stats.closed += part.stats.closed;
+        stats.memory += part.stats.memory;
+    }
+
+    let best = parts.iter().enumerate()
+        .filter_map(|(t, p)| p.goal.map(|i| (p.links[i].1, t, i)))
+        .min_by_key(|&(g, _, _)| g);
+    let solution = best.map(|(g, t, i)| Solution { plan: extract(&parts, (t, i)), cost: g });
+
+    let limit = *shared.limit.lock().unwrap();
+    stats.termination = Some(match limit {
+        Some(t) => t,
+        None if solution.is_some() => Termination::Solved,
+        None => Termination::Exhausted
+    });
+
+    Outcome { solution: solution, stats: stats }
+}
+
+fn extract(parts: &[Part], goal: Link) -> Vec<Step> {
+    let mut plan = Vec::new();
+    let mut link = goal;
+    while let Some((parent, step)) = parts[link.0].links[link.1].0 {
+        plan.push(step);
+        link = parent;
+    }
+    plan.reverse();
+    plan
+}
+
+struct Worker<'a, 'p: 'a, H: 'a> {
+    me: usize,
+    problem: &'a LevelProblem<'p>,
+    heuristic: &'a H,
+    shared: &'a Shared,
+    monitor: Monitor,
+    records: Vec<Record>,
+    seen: HashMap<State, usize>,
+    open: Priority,
+    goal: Option<usize>,
+    idle: bool
+}
+
+impl<'a, 'p, H: Heuristic<State>> Worker<'a, 'p, H> {
+    fn new(me: usize, problem: &'a LevelProblem<'p>, heuristic: &'a H, shared: &'a Shared, limits: &Limits)
+        -> Worker<'a, 'p, H>
+    {
+        Worker {
+            me: me, problem: problem, heuristic: heuristic, shared: shared,
+            monitor: Monitor::new(limits),
+            records: Vec::new(), seen: HashMap::new(), open: Priority::new(),
+            goal: None, idle: false
+        }
+    }
+
+    fn run(mut self, receiver: Receiver<Message>) -> Part {
+        let node_size = 2 * self.problem.start().mem_size() + size_of::<Record>() + size_of::<usize>();
+        let mut children = Vec::new();
+
+        loop {
+            while let Ok(message) = receiver.try_recv() { self.receive(message); }
+            if self.shared.stop.load(Ordering::Relaxed) { break; }
+
+            let entry = match self.next() {
+                Some(entry) => entry,
+                None => {
+                    if !self.idle {
+                        self.idle = true;
+                        self.shared.work.fetch_sub(1, Ordering::SeqCst);
+                    }
+                    if self.shared.work.load(Ordering::SeqCst) == 0 { break; }
+                    if let Ok(message) = receiver.recv_timeout(Duration::from_millis(1)) { self.receive(message); }
+                    continue;
+                }
+            };
+
+            let i = entry.index;
+            if self.problem.is_goal(&self.records[i].node) {
+                self.improve(entry.g as usize);
+                self.goal = Some(i);
+                continue;
+            }
+
+            let memory = self.records.len() * node_size + self.open.len() * size_of::<Entry>();
+            if let Some(t) = self.monitor.expand(self.open.len(), self.records.len(), memory) {
+                let mut limit = self.shared.limit.lock().unwrap();
+                if limit.is_none() { *limit = Some(t); }
+                self.shared.stop.store(true, Ordering::Relaxed);
+                break;
+            }
+
+            self.problem.expand(&self.records[i].node, &mut children);
+            self.monitor.generated(children.len());
+
+            for (step, child, cost) in children.drain(..) {
+                let message = (child, entry.g + cost, Some(((self.me, i), step)));
+                let owner = message.0.hash() as usize % self.shared.senders.len();
+
+                if owner == self.me {
+                    self.insert(message);
+                } else {
+                    self.shared.work.fetch_add(1, Ordering::SeqCst);
+                    if self.shared.senders[owner].send(message).is_err() {
+                        self.shared.work.fetch_sub(1, Ordering::SeqCst);
+                    }
+                }
+            }
+        }
+
+        let stats = self.monitor.finish::<()>(Termination::Exhausted, None).stats;
+        Part {
+            links: self.records.into_iter().map(|r| (r.parent, r.g)).collect(),
+            goal: self.goal,
+            stats: stats
+        }
+    }
+
+    // open entry under the incumbent, skipping stale ones
+    fn next(&mut self) -> Option<Entry> {
+        let incumbent = self.shared.incumbent.load(Ordering::SeqCst);
+        while let Some(entry) = self.open.pop() {
+            let record = &self.records[entry.index];
+            if record.g != entry.g { continue; }
+            if (entry.g + record.h) as usize >= incumbent { continue; }
+            return Some(entry);
+        }
+        None
+    }
+
+    fn receive(&mut self, message: Message) {
+        if self.idle {
+            self.idle = false;
+            self.shared.work.fetch_add(1, Ordering::SeqCst);
+        }
+        self.insert(message);
+        self.shared.work.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    fn insert(&mut self, (node, g, parent): Message) {
+        let i = if let Some(&i) = self.seen.get(&node) {
+            if self.records[i].g <= g { return; }
+            self.records[i].g = g;
+            self.records[i].parent = parent;
+            i
+        } else {
+            let h = self.heuristic.estimate(&node);
+            self.seen.insert(node.clone(), self.records.len());
+            self.records.push(Record { node: node, parent: parent, g: g, h: h });
+            self.records.len() - 1
+        };
+
+        let h = self.records[i].h;
+        if (g + h) as usize >= self.shared.incumbent.load(Ordering::SeqCst) { return; }
+        self.open.push(Entry { f: (g + h) as f64, h: h, g: g, index: i });
+    }
+
+    // lowers the incumbent to `cost`
+    fn improve(&self, cost: usize) {
+        let mut current = self.shared.incumbent.load(Ordering::SeqCst);
+        while cost < current {
+            match self.shared.incumbent.compare_exchange(current, cost, Ordering::SeqCst, Ordering::SeqCst) {
+                Ok(_) => break,
+                Err(actual) => current = actual
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use search::graph::bfs;
+
+    #[test]
+    fn optimal_plans() {
+        let level = Arc::new(Level::new("++++++++\n+0 A  a+\n+ ++++ +\n+  B  b+\n++++++++\n"));
+        let problem = LevelProblem::new(&level);
+        let optimal = bfs(&problem, &Limits::new()).solution.unwrap().cost;
+
+        for &threads in &[1, 2, 4] {
+            let outcome = hdastar(level.clone(), None, threads, &Limits::new());
+            assert_eq!(outcome.stats.termination, Some(Termination::Solved));
+
+            let solution = outcome.solution.unwrap();
+            assert_eq!(solution.cost, optimal);
+
+            let mut state = problem.start().clone();
+            for &(agent, action) in &solution.plan { state = state.apply(agent, action).unwrap(); }
+            assert!(state.is_goal_state(&level));
+        }
+    }
+
+    #[test]
+    fn exhausted_and_limited() {
+        let level = Arc::new(Level::new("++++++\n+A0 a+\n++++++\n"));
+        let outcome = hdastar(level, None, 3, &Limits::new());
+        assert_eq!(outcome.stats.termination, Some(Termination::Exhausted));
+        assert!(outcome.solution.is_none());
+
+        let level = Arc::new(Level::new("+++++++\n+AA+0a+\n+++++a+\n+++++++\n"));
+        let outcome = hdastar(level, None, 2, &Limits::new());
+        assert_eq!(outcome.stats.termination, Some(Termination::Exhausted));
+        assert_eq!(outcome.stats.expanded, 0);
+
+        let level = Arc::new(Level::new("++++++++\n+0 A  a+\n+ ++++ +\n+  B  b+\n++++++++\n"));
+        let outcome = hdastar(level, None, 2, &Limits::new().max_expanded(4));
+        assert_eq!(outcome.stats.termination, Some(Termination::ExpansionLimit));
+    }
+}
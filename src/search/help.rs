@@ -0,0 +1,399 @@
+use std::mem::size_of;
+
+use defs::grid::Grid;
+use defs::pos::{Pos, NULL_POS};
+use level::distance::Distances;
+use level::goal::Goal;
+use level::item::Item;
+use level::level::Level;
+use level::order::GoalOrder;
+use state::action::Action;
+use state::state::State;
+use super::agent::LOW_LEVEL_LIMIT;
+use super::allocation::Tasks;
+use super::graph::{astar, Solution};
+use super::heuristic::Heuristic;
+use super::matching::UNREACHABLE;
+use super::parking::Parking;
+use super::problem::{Problem, Step};
+use super::stats::{Limits, Monitor, Outcome, Termination};
+
+/// Longest chain of help requests, the agent of the task included.
+pub const MAX_DEPTH: usize = 8;
+
+/// Cost of a step onto a cell that another agent has to clear first, so that plans go around
+/// the others when they can.
+const HELP_COST: u32 = 20;
+
+/// Cells that `requester` needs cleared of the agent and the boxes of `helper`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HelpRequest {
+    pub requester: usize,
+    pub helper: usize,
+    pub cells: Vec<Pos>
+}
+
+/// Result of cooperative planning: the plan, and the help requests that were served.
+pub struct Cooperation {
+    pub outcome: Outcome<Solution<Step>>,
+    pub requests: Vec<HelpRequest>
+}
+
+/// Decoupled planning with help requests. The tasks of the allocation are done one after the
+/// other, lowest goal rank first. Each agent plans alone, as if the agents and boxes it cannot
+/// move were not there; those standing on its path are then asked to leave it, the agent of
+/// the box color planning in turn to move its things off the cells needed, the boxes to
+/// parking spots. Helpers blocked themselves ask for help, up to `MAX_DEPTH` agents. Filled
+/// goals are kept filled.
+///
+/// Agents act one at a time, the plan is sequential. A task that fails goes to the end of the
+/// queue; planning stops when a whole pass over the queue makes no progress.
+pub fn cooperative(level: &Level, limits: &Limits) -> Cooperation {
+    let start = State::new(level);
+    let tables = Distances::all(level);
+    let order = GoalOrder::new(level);
+    let tasks = Tasks::hungarian(level, &start, &tables);
+
+    let mut low_limits = limits.clone().max_expanded(LOW_LEVEL_LIMIT);
+    low_limits.progress = None;
+
+    let mut queue = tasks.agents.iter().enumerate()
+        .flat_map(|(a, list)| list.iter().map(move |t| (a, t.goal)))
+        .collect::<Vec<(usize, usize)>>();
+    queue.sort_by_key(|&(_, g)| order.rank(g));
+
+    let mut planner = Planner {
+        level: level,
+        parking: Parking::new(level),
+        tables: &tables,
+        limits: &low_limits,
+        monitor: Monitor::new(limits),
+        state: start,
+        done: Vec::new(),
+        plan: Vec::new(),
+        requests: Vec::new()
+    };
+
+    let mut failures = 0;
+    while !queue.is_empty() && failures < queue.len() {
+        if let Some(t) = planner.monitor.expand(queue.len(), planner.done.len(), planner.mem_size()) {
+            return planner.finish(t);
+        }
+
+        let (agent, g) = queue.remove(0);
+        let goal = level.goals()[g];
+        if planner.serve(agent, &Target::Fill(goal), &[], &mut vec!(agent)) {
+            planner.done.push(goal);
+            failures = 0;
+        } else {
+            queue.push((agent, g));
+            failures += 1;
+        }
+    }
+
+    let termination = if planner.state.is_goal_state(level) { Termination::Solved } else { Termination::Exhausted };
+    planner.finish(termination)
+}
+
+/// What a single agent plans for.
+enum Target {
+    Fill(Goal),             // the goal is satisfied
+    Clear(Vec<Pos>, Grid<bool>)     // none of the cells holds the agent or one of its boxes, and
+                                    // the boxes it moved stand on the parking spots given
+}
+
+struct Planner<'a> {
+    level: &'a Level,
+    parking: Parking,
+    tables: &'a [Distances],
+    limits: &'a Limits,
+    monitor: Monitor,
+    state: State,
+    done: Vec<Goal>,            // filled goals, kept filled
+    plan: Vec<Step>,
+    requests: Vec<HelpRequest>
+}
+
+impl<'a> Planner<'a> {
+    // bytes used by the current state, the plan so far and the requests served
+    fn mem_size(&self) -> usize {
+        self.state.mem_size() + self.done.len() * size_of::<Goal>() + self.plan.len() * size_of::<Step>()
+            + self.requests.iter().map(|r| size_of::<HelpRequest>() + r.cells.len() * size_of::<Pos>()).sum::<usize>()
+    }
+
+    /// Plans `agent` for `target`, gets the cells on its way cleared, and runs the plan. The
+    /// helpers also keep clear the cells in `keep`, needed further up the chain. `busy` are the
+    /// agents waiting on this one, which cannot help.
+    fn serve(&mut self, agent: usize, target: &Target, keep: &[Pos], busy: &mut Vec<usize>) -> bool {
+        let actions = match self.solve(agent, target, busy) {
+            Some(actions) => actions,
+            None => return false
+        };
+
+        // cells used by the plan, and the objects of other agents in the way
+        let mut path = Vec::new();
+        let mut relaxed = self.relaxed(agent, busy);
+        for &action in &actions {
+            let effect = action.effect(relaxed.agent(agent));
+            path.push(effect.agent_to);
+            if let Some((_, to)) = effect.box_move { path.push(to); }
+            relaxed = relaxed.apply(agent, action).unwrap();
+        }
+        path.sort_by_key(|p| (p.row, p.col));
+        path.dedup();
+
+        let mut helpers = Vec::<(usize, Vec<Pos>)>::new();
+        for &cell in &path {
+            let helper = match self.blocker(agent, cell, busy) {
+                Some(Ok(helper)) => helper,
+                Some(Err(())) => return false,
+                None => continue
+            };
+            if busy.contains(&helper) || busy.len() >= MAX_DEPTH { return false; }
+            match helpers.iter().position(|&(h, _)| h == helper) {
+                Some(i) => helpers[i].1.push(cell),
+                None => helpers.push((helper, vec!(cell)))
+            }
+        }
+
+        let mut cleared = keep.to_vec();
+        cleared.extend(path.iter().cloned());
+        for (helper, cells) in helpers {
+            // the boxes in the way are parked off every cell needed
+            let (rows, cols) = self.state.size();
+            let mut spots = Grid::<bool>::new(rows, cols);
+            for &cell in cells.iter().filter(|&&c| self.state[c].is_box()) {
+                for (spot, _) in self.parking.spots(self.level, &self.state, cell, &cleared) { spots[spot] = true; }
+            }
+
+            self.requests.push(HelpRequest { requester: agent, helper: helper, cells: cells });
+            busy.push(helper);
+            let served = self.serve(helper, &Target::Clear(cleared.clone(), spots), &cleared, busy);
+            busy.pop();
+            if !served { return false; }
+        }
+
+        for action in actions {
+            match self.state.apply(agent, action) {
+                Some(next) => self.state = next,
+                None => return false
+            }
+            self.plan.push((agent, action));
+        }
+        true
+    }
+
+    /// Agent to clear `cell` for `agent`: None if the cell is free for it, an error if nobody
+    /// can clear it. Boxes go to the closest agent of their color not in `busy`.
+    fn blocker(&self, agent: usize, cell: Pos, busy: &[usize]) -> Option<Result<usize, ()>> {
+        let item = self.state[cell];
+        let own = self.state[self.state.agent(agent)];
+
+        if item.is_agent() && item.id() as usize != agent { return Some(Ok(item.id() as usize)); }
+        if !item.is_box() || Item::compatible(&own, &item) { return None; }
+
+        let closest = self.state.agents().iter().enumerate()
+            .filter(|&(a, &p)| p != NULL_POS && !busy.contains(&a) && self.state[p].color() == item.color())
+            .filter_map(|(a, &p)| distance(self.tables, p, cell).map(|d| (d, a)))
+            .min();
+        Some(closest.map(|(_, a)| a).ok_or(()))
+    }
+
+    /// The state as seen by `agent`: the other agents are gone, and so are the boxes it cannot
+    /// move but another agent can, unless they sit on filled goals. The agents in `busy` wait
+    /// for this one, they and their boxes stay.
+    fn relaxed(&self, agent: usize, busy: &[usize]) -> State {
+        let mut world = self.state.clone();
+        let own = self.state[self.state.agent(agent)];
+        let colors = self.state.agents().iter().enumerate()
+            .filter(|&(a, &p)| p != NULL_POS && !busy.contains(&a)).map(|(_, &p)| self.state[p].color()).collect::<Vec<_>>();
+        for (other, &pos) in self.state.agents().iter().enumerate() {
+            if other != agent && pos != NULL_POS && !busy.contains(&other) { world.remove(pos); }
+        }
+
+        let (rows, cols) = world.size();
+        for row in 0..rows {
+            for col in 0..cols {
+                let pos = Pos::new(row as i8, col as i8);
+                let item = world[pos];
+                if item.is_box() && !Item::compatible(&own, &item) && colors.contains(&item.color())
+                   && !self.done.iter().any(|g| g.pos == pos) {
+                    world.remove(pos);
+                }
+            }
+        }
+        world
+    }
+
+    fn solve(&mut self, agent: usize, target: &Target, busy: &[usize]) -> Option<Vec<Action>> {
+        let start = self.relaxed(agent, busy);
+        let (rows, cols) = start.size();
+        let mut blocked = Grid::<bool>::new(rows, cols);
+        for row in 0..rows {
+            for col in 0..cols {
+                let pos = Pos::new(row as i8, col as i8);
+                blocked[pos] = start[pos] != self.state[pos];
+            }
+        }
+
+        let problem = SoloProblem {
+            agent: agent,
+            start: start,
+            blocked: blocked,
+            target: target,
+            done: &self.done,
+            actions: Action::all().into_iter().filter(|&a| a != Action::NoOp).collect()
+        };
+        let h = SoloHeuristic { agent: agent, target: target, done: &self.done, tables: self.tables };
+        let outcome = astar(&problem, &h, self.limits);
+        self.monitor.generated(outcome.stats.expanded as usize);
+        outcome.solution.map(|s| s.plan)
+    }
+
+    fn finish(self, termination: Termination) -> Cooperation {
+        let solution = if termination == Termination::Solved {
+            Some(Solution { cost: self.plan.len() as u32, plan: self.plan })
+        } else {
+            None
+        };
+        Cooperation { outcome: self.monitor.finish(termination, solution), requests: self.requests }
+    }
+}
+
+/// One agent alone in its relaxed world, leaving filled goals filled. Cells cleared by others
+/// cost more.
+struct SoloProblem<'a> {
+    agent: usize,
+    start: State,
+    blocked: Grid<bool>,        // cells that others have to clear
+    target: &'a Target,
+    done: &'a [Goal],
+    actions: Vec<Action>
+}
+
+impl<'a> SoloProblem<'a> {
+    // whether every box of the agent that moved stands on a parking spot
+    fn parked(&self, state: &State, spots: &Grid<bool>) -> bool {
+        let own = state[state.agent(self.agent)];
+        let (rows, cols) = state.size();
+        for row in 0..rows {
+            for col in 0..cols {
+                let pos = Pos::new(row as i8, col as i8);
+                let item = state[pos];
+                if item.is_box() && Item::compatible(&own, &item) && item != self.start[pos] && !spots[pos] { return false; }
+            }
+        }
+        true
+    }
+}
+
+impl<'a> Problem for SoloProblem<'a> {
+    type Node = State;
+    type Action = Action;
+    type Key = State;
+
+    fn initial(&self) -> Vec<State> { vec!(self.start.clone()) }
+
+    fn is_goal(&self, state: &State) -> bool {
+        let reached = match *self.target {
+            Target::Fill(ref goal) => state.satisfies(goal),
+            Target::Clear(ref cells, ref spots) => {
+                cells.iter().all(|&c| !is_object(state, self.agent, c)) && self.parked(state, spots)
+            }
+        };
+        reached && self.done.iter().all(|g| state.satisfies(g))
+    }
+
+    fn expand(&self, state: &State, out: &mut Vec<(Action, State, u32)>) {
+        for &action in &self.actions {
+            if let Some(next) = state.apply(self.agent, action) {
+                let effect = action.effect(state.agent(self.agent));
+                let helped = self.blocked[effect.agent_to] || effect.box_move.is_some_and(|(_, to)| self.blocked[to]);
+                out.push((action, next, if helped { HELP_COST } else { 1 }));
+            }
+        }
+    }
+
+    fn node_size(&self, state: &State) -> usize { state.mem_size() }
+
+    fn key(&self, state: &State) -> State { state.clone() }
+
+    fn key_size(&self, state: &State) -> usize { state.mem_size() }
+}
+
+/// Distance of the closest fitting box to the goal plus the walk to it, boxes on filled goals
+/// aside, or the number of cells left to clear.
+struct SoloHeuristic<'a> {
+    agent: usize,
+    target: &'a Target,
+    done: &'a [Goal],
+    tables: &'a [Distances]
+}
+
+impl<'a> Heuristic<State> for SoloHeuristic<'a> {
+    fn estimate(&self, state: &State) -> u32 {
+        match *self.target {
+            Target::Clear(ref cells, _) => cells.iter().filter(|&&c| is_object(state, self.agent, c)).count() as u32,
+            Target::Fill(ref goal) if state.satisfies(goal) => 0,
+            Target::Fill(ref goal) => {
+                let agent = state.agent(self.agent);
+                let (rows, cols) = state.size();
+                let mut best = UNREACHABLE;
+                for row in 0..rows {
+                    for col in 0..cols {
+                        let pos = Pos::new(row as i8, col as i8);
+                        if !goal.is_satisfied_by(state[pos]) || self.done.iter().any(|g| g.pos == pos) { continue; }
+                        if let (Some(d), Some(w)) = (distance(self.tables, pos, goal.pos), distance(self.tables, agent, pos)) {
+                            best = best.min(d + w.saturating_sub(1));
+                        }
+                    }
+                }
+                best
+            }
+        }
+    }
+}
+
+// whether `cell` holds `agent` or one of the boxes it can move
+fn is_object(state: &State, agent: usize, cell: Pos) -> bool {
+    let item = state[cell];
+    let own = state[state.agent(agent)];
+    (item.is_agent() && item.id() as usize == agent) || (item.is_box() && Item::compatible(&own, &item))
+}
+
+fn distance(tables: &[Distances], a: Pos, b: Pos) -> Option<u32> {
+    tables.iter().find(|t| t.contains(a) && t.contains(b)).and_then(|t| t.between(a, b)).map(|d| d as u32)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn chained_requests() {
+        // B blocks the corridor of agent 0, and G the way of agent 1 to B
+        let level = Level::new("red: 0, A\nblue: 1, B\ngreen: 2, G\n+++++++++\n+0A B  a+\n+++ G2 ++\n++++1+ ++\n+++++++++\n");
+        let result = cooperative(&level, &Limits::new());
+        let solution = result.outcome.solution.expect("no plan");
+
+        let mut state = State::new(&level);
+        for &(agent, action) in &solution.plan {
+            state = state.apply(agent, action).expect("invalid plan");
+        }
+        assert!(state.is_goal_state(&level));
+
+        let helpers = result.requests.iter().map(|r| (r.requester, r.helper)).collect::<Vec<(usize, usize)>>();
+        assert_eq!(helpers, vec!((0, 1), (1, 2)));
+        assert_eq!(result.requests[0].cells, vec!(Pos::new(1, 4)));
+
+        // the boxes moved out of the way are parked
+        let parking = Parking::new(&level);
+        let (rows, cols) = state.size();
+        for row in 0..rows {
+            for col in 0..cols {
+                let pos = Pos::new(row as i8, col as i8);
+                if state[pos].is_box() && state[pos] != level[pos] { assert!(!parking.is_excluded(pos), "{:?}", pos); }
+            }
+        }
+    }
+}
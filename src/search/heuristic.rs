@@ -0,0 +1,35 @@
+use level::level::Level;
+use state::state::State;
+
+/// Estimated cost from a node to the closest goal.
+pub trait Heuristic<N> {
+    fn estimate(&self, node: &N) -> u32;
+}
+
+/// Always 0, turns A* into uniform cost search.
+pub struct Zero;
+
+impl<N> Heuristic<N> for Zero {
+    fn estimate(&self, _: &N) -> u32 { 0 }
+}
+
+/// Number of unsatisfied box goals, or of unsatisfied agent goals if larger. Admissible for
+/// the sequential formulation: a step moves one agent and at most one box, so it satisfies at
+/// most one goal of each kind.
+pub struct GoalCount<'a> {
+    level: &'a Level
+}
+
+impl<'a> GoalCount<'a> {
+    pub fn new(level: &'a Level) -> GoalCount<'a> { GoalCount { level: level } }
+}
+
+impl<'a> Heuristic<State> for GoalCount<'a> {
+    fn estimate(&self, state: &State) -> u32 {
+        let (boxes, agents) = self.level.goals().iter()
+            .filter(|g| !state.satisfies(g))
+            .fold((0, 0), |(b, a), g| if g.is_box_goal() { (b + 1, a) } else { (b, a + 1) });
+        boxes.max(agents)
+    }
+}
+
@@ -0,0 +1,163 @@
+use std::mem::size_of;
+
+use state::packed::PackedState;
+use state::state::State;
+use super::graph::Solution;
+use super::heuristic::Heuristic;
+use super::problem::{LevelProblem, Problem, Step};
+use super::stats::{Limits, Monitor, Outcome, Termination};
+
+/// Iterative-deepening A*. A single state is mutated with `apply_in_place`/`undo`, so memory
+/// is bounded by the transposition table, which holds `table_size` packed states.
+pub fn idastar<H>(problem: &LevelProblem, heuristic: &H, table_size: usize, limits: &Limits)
+    -> Outcome<Solution<Step>> where H: Heuristic<State>
+{
+    let mut ida = Ida {
+        problem: problem,
+        heuristic: heuristic,
+        table: vec!(Slot { key: None, g: 0, iteration: 0 }; table_size.max(1)),
+        key_size: problem.key(problem.start()).mem_size(),
+        iteration: 0,
+        path: Vec::new(),
+        monitor: Monitor::new(limits)
+    };
+
+    // nothing to search from a start already deadlocked
+    let mut state = match problem.initial().pop() {
+        Some(state) => state,
+        None => return ida.monitor.finish(Termination::Exhausted, None)
+    };
+    let mut bound = heuristic.estimate(&state);
+
+    loop {
+        ida.iteration += 1;
+        match ida.dfs(&mut state, 0, bound) {
+            Iteration::Found => {
+                let plan = ida.path;
+                let solution = Solution { cost: plan.len() as u32, plan: plan };
+                return ida.monitor.finish(Termination::Solved, Some(solution));
+            }
+            Iteration::Stopped(t) => return ida.monitor.finish(t, None),
+            Iteration::Next(u32::MAX) => return ida.monitor.finish(Termination::Exhausted, None),
+            Iteration::Next(next) => bound = next
+        }
+    }
+}
+
+enum Iteration {
+    Found,                  // the plan is in `Ida::path`
+    Next(u32),              // smallest f that exceeded the bound, u32::MAX if none did
+    Stopped(Termination)
+}
+
+#[derive(Clone)]
+struct Slot {
+    key: Option<PackedState>,
+    g: u32,
+    iteration: u32          // 0 is never used by a search iteration, so fresh slots are empty
+}
+
+struct Ida<'p, 'a: 'p, H: 'p> {
+    problem: &'p LevelProblem<'a>,
+    heuristic: &'p H,
+    table: Vec<Slot>,
+    key_size: usize,        // bytes of one packed state, all have the same size
+    iteration: u32,
+    path: Vec<Step>,
+    monitor: Monitor
+}
+
+impl<'p, 'a, H> Ida<'p, 'a, H> where H: Heuristic<State> {
+    fn dfs(&mut self, state: &mut State, g: u32, bound: u32) -> Iteration {
+        let f = g + self.heuristic.estimate(state);
+
+        if f > bound { return Iteration::Next(f); }
+        if self.problem.is_goal(state) { return Iteration::Found; }
+        if !self.visit(state, g) { return Iteration::Next(u32::MAX); }
+
+        let memory = self.table.len() * (size_of::<Slot>() + self.key_size) + self.path.capacity() * size_of::<Step>();
+        if let Some(t) = self.monitor.expand(self.path.len(), self.table.len(), memory) {
+            return Iteration::Stopped(t);
+        }
+
+        let mut next = u32::MAX;
+        let actions = self.problem.actions();
+
+        for agent in 0..state.nb_agents() {
+            for &action in actions {
+                if self.problem.is_pruned(state, agent, action) { continue; }
+                if let Some(undo) = state.apply_in_place(agent, action) {
+                    if self.problem.is_deadlocked(state, agent, action) {
+                        state.undo(undo);
+                        continue;
+                    }
+                    self.monitor.generated(1);
+                    self.path.push((agent, action));
+                    let result = self.dfs(state, g + 1, bound);
+                    state.undo(undo);
+
+                    match result {
+                        Iteration::Next(b) => next = next.min(b),
+                        other => return other
+                    }
+                    self.path.pop();
+                }
+            }
+        }
+
+        Iteration::Next(next)
+    }
+
+    /// Records the state in the transposition table; false if it was already reached with a
+    /// cost no greater than `g` during this iteration.
+    fn visit(&mut self, state: &State, g: u32) -> bool {
+        let key = self.problem.key(state);
+        let index = (key.hash() % self.table.len() as u64) as usize;
+        let slot = &mut self.table[index];
+
+        if slot.iteration == self.iteration && slot.key.as_ref() == Some(&key) && slot.g <= g { return false; }
+
+        *slot = Slot { key: Some(key), g: g, iteration: self.iteration };
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use level::level::Level;
+    use search::graph::bfs;
+    use search::heuristic::GoalCount;
+
+    #[test]
+    fn optimal() {
+        let level = Level::from_file("levels/easy.lvl").unwrap();
+        let problem = LevelProblem::new(&level);
+        let h = GoalCount::new(&level);
+        let limits = Limits::new();
+        let optimal = bfs(&problem, &limits).solution.unwrap().cost;
+
+        for &size in &[1, 64, 1 << 16] {
+            let solution = idastar(&problem, &h, size, &limits).solution.unwrap();
+            assert_eq!(solution.cost, optimal);
+
+            let mut state = problem.start().clone();
+            for &(agent, action) in &solution.plan { state = state.apply(agent, action).unwrap(); }
+            assert!(state.is_goal_state(&level));
+        }
+    }
+
+    #[test]
+    fn unsolvable() {
+        let level = Level::new("++++++\n+A0 a+\n++++++\n");
+        let problem = LevelProblem::new(&level);
+        let outcome = idastar(&problem, &GoalCount::new(&level), 1024, &Limits::new());
+        assert!(outcome.solution.is_none());
+        assert_eq!(outcome.stats.termination, Some(Termination::Exhausted));
+        // frozen boxes at the start: nothing is expanded
+        let level = Level::new("+++++++\n+AA+0a+\n+++++a+\n+++++++\n");
+        let outcome = idastar(&LevelProblem::new(&level), &GoalCount::new(&level), 1024, &Limits::new());
+        assert_eq!(outcome.stats.termination, Some(Termination::Exhausted));
+        assert_eq!(outcome.stats.expanded, 0);
+    }
+}
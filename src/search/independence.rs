@@ -0,0 +1,268 @@
+use std::mem::size_of;
+
+use defs::pos::{Pos, NULL_POS};
+use level::dead::DeadSquares;
+use level::goal::Goal;
+use level::item::Color;
+use level::level::Level;
+use state::action::Action;
+use state::joint::JointAction;
+use state::state::State;
+use super::graph::{astar, Solution};
+use super::matching::Matching;
+use super::problem::{Problem, Step};
+use super::stats::{Limits, Monitor, Outcome, Termination};
+
+/// Agents planned together. Agents of one color share their boxes, so colors are never split.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Group {
+    pub colors: Vec<Color>,
+    pub agents: Vec<usize>
+}
+
+/// Result of independence detection: the final groups and the merged plan.
+pub struct Decomposition {
+    pub groups: Vec<Group>,
+    pub outcome: Outcome<Solution<JointAction>>
+}
+
+/// Independence detection (Standley): every color is first planned on its own, with A* over
+/// a level without the other agents and their boxes. The group plans are then run together
+/// with the joint-action executor; the two groups behind the first failing action are merged
+/// and planned jointly, until the plans no longer interfere. A failure no other group explains
+/// merges all of them.
+///
+/// Groups are planned sequentially, one agent acting per step, so the joint plan is optimal
+/// for no objective once groups are merged.
+pub fn independence(level: &Level, limits: &Limits) -> Decomposition {
+    let start = State::new(level);
+    let dead = DeadSquares::new(level);
+    let mut monitor = Monitor::new(limits);
+
+    let mut low_limits = limits.clone();
+    low_limits.progress = None;
+
+    let mut groups = Vec::<Group>::new();
+    for (a, &pos) in start.agents().iter().enumerate() {
+        if pos == NULL_POS { continue; }
+        let color = start[pos].color();
+        match groups.iter().position(|g| g.colors[0] == color) {
+            Some(g) => groups[g].agents.push(a),
+            None => groups.push(Group { colors: vec!(color), agents: vec!(a) })
+        }
+    }
+
+    let mut plans = Vec::new();
+    for group in &groups {
+        match plan_group(level, &start, &dead, group, &low_limits) {
+            Ok(plan) => plans.push(plan),
+            Err(t) => return Decomposition { groups: groups, outcome: monitor.finish(t, None) }
+        }
+    }
+
+    loop {
+        let memory = plans.iter().map(|p| p.len() * size_of::<Step>()).sum::<usize>()
+            + groups.iter().map(|g| g.colors.len() * size_of::<Color>() + g.agents.len() * size_of::<usize>()).sum::<usize>();
+        if let Some(t) = monitor.expand(0, groups.len(), memory) {
+            return Decomposition { groups: groups, outcome: monitor.finish(t, None) };
+        }
+
+        let plan = merge(&plans, start.nb_agents());
+        let mut merged = match first_conflict(&start, &plan, &groups) {
+            None => {
+                let mut state = start.clone();
+                for joint in &plan { joint.execute(&mut state); }
+                let outcome = if state.is_goal_state(level) {
+                    monitor.finish(Termination::Solved, Some(Solution { cost: plan.len() as u32, plan: plan }))
+                } else {
+                    monitor.finish(Termination::Incomplete, None)
+                };
+                return Decomposition { groups: groups, outcome: outcome };
+            }
+            Some(conflict) => conflict
+        };
+
+        // a single group failing on its own cannot be helped by merging
+        merged.sort();
+        merged.dedup();
+        if merged.len() < 2 {
+            return Decomposition { groups: groups, outcome: monitor.finish(Termination::Incomplete, None) };
+        }
+
+        let i = merged[0];
+        for &j in merged[1..].iter().rev() {
+            let other = groups.remove(j);
+            plans.remove(j);
+            groups[i].colors.extend(other.colors);
+            groups[i].agents.extend(other.agents);
+        }
+        groups[i].agents.sort();
+        monitor.generated(1);
+
+        match plan_group(level, &start, &dead, &groups[i], &low_limits) {
+            Ok(plan) => plans[i] = plan,
+            Err(t) => return Decomposition { groups: groups, outcome: monitor.finish(t, None) }
+        }
+    }
+}
+
+fn plan_group(level: &Level, start: &State, dead: &DeadSquares, group: &Group, limits: &Limits)
+              -> Result<Vec<Step>, Termination> {
+    let problem = GroupProblem::new(level, start, dead, group);
+    let h = Matching::with_colors(level, &group.colors);
+    let outcome = astar(&problem, &h, limits);
+    outcome.solution.map(|s| s.plan).ok_or(outcome.stats.termination.unwrap_or(Termination::Exhausted))
+}
+
+// the group plans side by side, one step of each group per joint action
+fn merge(plans: &[Vec<Step>], nb_agents: usize) -> Vec<JointAction> {
+    let length = plans.iter().map(|p| p.len()).max().unwrap_or(0);
+    (0..length).map(|t| {
+        let mut actions = vec!(Action::NoOp; nb_agents);
+        for &(agent, action) in plans.iter().filter_map(|p| p.get(t)) { actions[agent] = action; }
+        JointAction::new(actions)
+    }).collect()
+}
+
+/// Runs `plan` until an action fails, and returns the group of the failing agent with the
+/// groups of the agents clashing with it, or of the agent or box in its way, or every group
+/// when there is none.
+fn first_conflict(start: &State, plan: &[JointAction], groups: &[Group]) -> Option<Vec<usize>> {
+    let agent_group = |a: usize| groups.iter().position(|g| g.agents.contains(&a)).unwrap();
+    let color_group = |c: Color| groups.iter().position(|g| g.colors.contains(&c));
+    let mut state = start.clone();
+
+    for joint in plan {
+        if let Some(i) = joint.outcome(&state).iter().position(|&ok| !ok) {
+            // clashing with other applicable actions at the same step, all of them fail
+            let mut clashing = vec!(agent_group(i));
+            for (k, l) in joint.conflicts(&state) {
+                if k != i && l != i { continue; }
+                let g = agent_group(k + l - i);
+                if !clashing.contains(&g) { clashing.push(g); }
+            }
+            if clashing.len() > 1 { return Some(clashing); }
+
+            let e = joint[i].effect(state.agent(i));
+            for c in e.destinations() {
+                // held by an agent or a box of another group
+                let item = state[c];
+                let holder = if item.is_agent() { Some(agent_group(item.id() as usize)) }
+                             else if item.is_box() { color_group(item.color()) }
+                             else { None };
+                if let Some(g) = holder {
+                    if g != agent_group(i) { return Some(vec!(agent_group(i), g)); }
+                }
+            }
+            return Some((0..groups.len()).collect());
+        }
+        joint.execute(&mut state);
+    }
+
+    None
+}
+
+/// Sequential search of one group, in a level where the other agents and the boxes they can
+/// move are gone. Boxes without an agent of their color stay, they never move anyway.
+struct GroupProblem<'a> {
+    start: State,
+    agents: Vec<usize>,
+    goals: Vec<Goal>,
+    dead: &'a DeadSquares,
+    actions: Vec<Action>
+}
+
+impl<'a> GroupProblem<'a> {
+    fn new(level: &Level, start: &State, dead: &'a DeadSquares, group: &Group) -> GroupProblem<'a> {
+        let movable = start.agents().iter().filter(|&&p| p != NULL_POS).map(|&p| start[p].color()).collect::<Vec<Color>>();
+        let mut world = start.clone();
+        let (rows, cols) = start.size();
+
+        for (a, &pos) in start.agents().iter().enumerate() {
+            if pos != NULL_POS && !group.agents.contains(&a) { world.remove(pos); }
+        }
+        for row in 0..rows {
+            for col in 0..cols {
+                let pos = Pos::new(row as i8, col as i8);
+                let item = start[pos];
+                if item.is_box() && movable.contains(&item.color()) && !group.colors.contains(&item.color()) {
+                    world.remove(pos);
+                }
+            }
+        }
+
+        GroupProblem {
+            start: world,
+            agents: group.agents.clone(),
+            goals: level.goals().iter().filter(|g| group.colors.contains(&g.color)).cloned().collect(),
+            dead: dead,
+            actions: Action::all().into_iter().filter(|&a| a != Action::NoOp).collect()
+        }
+    }
+}
+
+impl<'a> Problem for GroupProblem<'a> {
+    type Node = State;
+    type Action = Step;
+    type Key = State;
+
+    fn initial(&self) -> Vec<State> { vec!(self.start.clone()) }
+
+    fn is_goal(&self, state: &State) -> bool { self.goals.iter().all(|g| state.satisfies(g)) }
+
+    fn expand(&self, state: &State, out: &mut Vec<(Step, State, u32)>) {
+        for &agent in &self.agents {
+            for &action in &self.actions {
+                if let Some((from, to)) = action.effect(state.agent(agent)).box_move {
+                    if state.in_bounds(from) && self.dead.is_dead(state[from], to) { continue; }
+                }
+                if let Some(next) = state.apply(agent, action) {
+                    out.push(((agent, action), next, 1));
+                }
+            }
+        }
+    }
+
+    fn node_size(&self, state: &State) -> usize { state.mem_size() }
+
+    fn key(&self, state: &State) -> State { state.clone() }
+
+    fn key_size(&self, state: &State) -> usize { state.mem_size() }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use defs::dir::{EAST, WEST, NORTH};
+
+    #[test]
+    fn separate_rooms() {
+        let level = Level::new("red: 0, A\nblue: 1, B\n+++++++\n+0A a +\n+++++++\n+1B b +\n+++++++\n");
+        let result = independence(&level, &Limits::new());
+        assert_eq!(result.groups.len(), 2);
+        assert_eq!(result.outcome.solution.expect("no plan").cost, 2);
+    }
+
+    #[test]
+    fn merged_on_conflict() {
+        // agent 1 has nothing to do, but stands in the corridor agent 0 needs
+        let level = Level::new("red: 0, A\nblue: 1\n+++++++\n+0A 1a+\n++++ ++\n+++++++\n");
+        let result = independence(&level, &Limits::new());
+        assert_eq!(result.groups, vec!(Group { colors: vec!(Color::Red, Color::Blue), agents: vec!(0, 1) }));
+
+        let mut state = State::new(&level);
+        for joint in &result.outcome.solution.expect("no plan").plan {
+            assert!(joint.execute(&mut state).iter().all(|&ok| ok));
+        }
+        assert!(state.is_goal_state(&level));
+    }
+
+    #[test]
+    fn three_way_conflict() {
+        let level = Level::new("red: 0\nblue: 1\ngreen: 2\n+++++\n+0 1+\n++2++\n+++++\n");
+        let state = State::new(&level);
+        let groups = (0..3).map(|a| Group { colors: vec!(state[state.agent(a)].color()), agents: vec!(a) }).collect::<Vec<Group>>();
+        let plan = vec!(JointAction::new(vec!(Action::Move(EAST), Action::Move(WEST), Action::Move(NORTH))));
+        assert_eq!(first_conflict(&state, &plan, &groups), Some(vec!(0, 1, 2)));
+    }
+}
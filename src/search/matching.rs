@@ -0,0 +1,152 @@
+use std::collections::HashMap;
+
+use na::core::DMatrix;
+
+use defs::assignment::min_cost_assignment;
+use defs::pos::{Pos, NULL_POS};
+use level::distance::Distances;
+use level::goal::Target;
+use level::item::Color;
+use level::level::Level;
+use state::state::State;
+use super::heuristic::Heuristic;
+
+/// Cost of a goal that no box can reach; large enough to rank such states last.
+pub const UNREACHABLE: u32 = 10_000;
+
+/// Boxes are matched to goals of their letter with minimal total distance (Hungarian
+/// algorithm over the distance table of each component), so that identical boxes do not
+/// all count the same nearest goal. For every color with boxes left to move, the distance
+/// from the closest agent of that color to the closest such box is added.
+///
+/// Admissible in the sequential formulation (one agent acts per step): each step moves at
+/// most one box by one cell, and an agent has to walk up to a box before moving it.
+pub struct Matching {
+    tables: Vec<Distances>,
+    groups: Vec<Group>
+}
+
+/// Goals of one box letter and color in one component.
+struct Group {
+    table: usize,
+    letter: u8,
+    color: Color,
+    goals: Vec<Pos>
+}
+
+impl Matching {
+    pub fn new(level: &Level) -> Matching {
+        Self::with_goals(level, |_| true)
+    }
+
+    /// Only counts the goals of `colors`, for searches moving the agents of those colors.
+    pub fn with_colors(level: &Level, colors: &[Color]) -> Matching {
+        Self::with_goals(level, |color| colors.contains(&color))
+    }
+
+    fn with_goals<F: Fn(Color) -> bool>(level: &Level, counted: F) -> Matching {
+        let tables = Distances::all(level);
+        let mut groups = Vec::<Group>::new();
+
+        for goal in level.goals().iter().filter(|g| counted(g.color)) {
+            let letter = match goal.target { Target::Box(l) => l, Target::Agent(_) => continue };
+            let table = match tables.iter().position(|t| t.contains(goal.pos)) { Some(t) => t, None => continue };
+
+            match groups.iter().position(|g| g.table == table && g.letter == letter && g.color == goal.color) {
+                Some(i) => groups[i].goals.push(goal.pos),
+                None => groups.push(Group { table: table, letter: letter, color: goal.color, goals: vec!(goal.pos) })
+            }
+        }
+
+        Matching { tables: tables, groups: groups }
+    }
+
+    pub fn tables(&self) -> &[Distances] { &self.tables }
+}
+
+impl Heuristic<State> for Matching {
+    fn estimate(&self, state: &State) -> u32 {
+        // boxes of each (component, letter, color)
+        let mut boxes = HashMap::<(usize, u8, Color), Vec<Pos>>::new();
+        for (t, table) in self.tables.iter().enumerate() {
+            let comp = table.component();
+            for i in 0..comp.nb_free_cells() {
+                let pos = comp.pos_of(i as i16);
+                let item = state[pos];
+                if item.is_box() { boxes.entry((t, item.id(), item.color())).or_default().push(pos); }
+            }
+        }
+
+        let mut total = 0;
+        let mut unsolved = HashMap::<Color, Vec<(usize, Pos)>>::new();
+
+        for group in &self.groups {
+            let table = &self.tables[group.table];
+            let candidates = match boxes.get(&(group.table, group.letter, group.color)) {
+                Some(b) => b,
+                None => { total += UNREACHABLE * group.goals.len() as u32; continue; }
+            };
+
+            let cost = DMatrix::from_fn(group.goals.len(), candidates.len(), |g, b| {
+                table.between(candidates[b], group.goals[g]).map_or(UNREACHABLE, |d| d as u32)
+            });
+            let (assignment, sum) = min_cost_assignment(&cost);
+
+            total += sum as u32;
+            total += UNREACHABLE * assignment.iter().filter(|a| a.is_none()).count() as u32;
+
+            for (g, b) in assignment.iter().enumerate() {
+                if let Some(b) = *b {
+                    if cost[(g, b)] > 0 {
+                        unsolved.entry(group.color).or_default().push((group.table, candidates[b]));
+                    }
+                }
+            }
+        }
+
+        for (&color, targets) in &unsolved {
+            let closest = state.agents().iter()
+                .filter(|&&a| a != NULL_POS && state[a].color() == color)
+                .flat_map(|&a| targets.iter().filter_map(move |&(t, b)| self.tables[t].between(a, b)))
+                .min();
+
+            total += match closest {
+                Some(d) => (d as u32).saturating_sub(1),
+                None    => UNREACHABLE
+            };
+        }
+
+        total
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use search::graph::{astar, bfs};
+    use search::problem::LevelProblem;
+    use search::stats::Limits;
+
+    #[test]
+    fn admissible_and_optimal() {
+        let level = Level::from_file("levels/easy.lvl").unwrap();
+        let problem = LevelProblem::new(&level);
+        let h = Matching::new(&level);
+        let optimal = bfs(&problem, &Limits::new()).solution.unwrap().cost;
+
+        assert!(h.estimate(problem.start()) <= optimal);
+        assert!(h.estimate(problem.start()) > 0);
+        assert_eq!(astar(&problem, &h, &Limits::new()).solution.unwrap().cost, optimal);
+    }
+
+    #[test]
+    fn identical_boxes() {
+        // both boxes are closest to the same goal, but only one of them can take it
+        let level = Level::new("++++++++\n+0AA  a+\n+     a+\n++++++++\n");
+        let h = Matching::new(&level);
+        let state = State::new(&level);
+
+        // A(1,2)->(1,6): 4, A(1,3)->(2,6): 4, agent next to a box: 0
+        assert_eq!(h.estimate(&state), 8);
+    }
+}
@@ -0,0 +1,24 @@
+pub mod problem;
+pub mod frontier;
+pub mod heuristic;
+pub mod graph;
+pub mod idastar;
+pub mod stats;
+pub mod strategy;
+pub mod anytime;
+pub mod matching;
+pub mod deadlock;
+pub mod patterns;
+pub mod backward;
+pub mod portfolio;
+pub mod hda;
+pub mod allocation;
+pub mod agent;
+pub mod reservation;
+pub mod cbs;
+pub mod prioritized;
+pub mod help;
+pub mod parking;
+pub mod schedule;
+pub mod optimize;
+pub mod independence;
@@ -0,0 +1,186 @@
+use defs::dir::DIRS;
+use defs::pos::Pos;
+use level::distance::Distances;
+use level::level::Level;
+use state::action::Action;
+use state::joint::JointAction;
+use state::simulator::{self, Simulator};
+use state::state::State;
+use super::problem::Step;
+use super::schedule::parallelize;
+
+/// Shortens a joint plan that solves `level`, keeping it valid:
+///  - an action followed by its inverse, the next action of the same agent, is dropped with it;
+///  - a run of moves of an agent is replaced by a shortest path between its ends, as long as
+///    the path is free when the agent walks it;
+///  - joint actions left without an action are dropped, trailing ones included;
+///  - the remaining steps are scheduled again as early as possible.
+///
+/// Every change is kept only if the plan still runs on the server `Simulator` and reaches the
+/// goal. A plan that does not solve the level is returned unchanged.
+pub fn optimize(level: &Level, plan: &[JointAction]) -> Vec<JointAction> {
+    let start = State::new(level);
+    if !is_valid(level, plan) { return plan.to_vec(); }
+    let tables = Distances::all(level);
+    let mut plan = plan.to_vec();
+
+    loop {
+        let size = (nb_actions(&plan), plan.len());
+        cancel_returns(level, &start, &mut plan);
+        shorten_detours(level, &start, &tables, &mut plan);
+        drop_noops(level, &mut plan);
+        if (nb_actions(&plan), plan.len()) == size { break; }
+    }
+
+    let steps = plan.iter().flat_map(|j| j.actions().iter().cloned().enumerate())
+        .filter(|&(_, a)| a != Action::NoOp).collect::<Vec<Step>>();
+    let scheduled = parallelize(&start, &steps);
+    if scheduled.len() <= plan.len() && is_valid(level, &scheduled) { scheduled } else { plan }
+}
+
+/// Whether the server accepts every joint action of `plan` from the start of `level`, and
+/// the level is solved in the end.
+pub fn is_valid(level: &Level, plan: &[JointAction]) -> bool {
+    let mut server = Simulator::new(level);
+    for joint in plan {
+        let success = simulator::reply(&vec!(true; joint.len()));
+        if server.step(&joint.to_string()) != Ok(success) { return false; }
+    }
+    server.is_solved()
+}
+
+// actions undone by the next action of their agent
+fn cancel_returns(level: &Level, start: &State, plan: &mut Vec<JointAction>) {
+    for agent in 0..start.nb_agents() {
+        let mut k = 0;
+        loop {
+            let times = active(plan, agent);
+            if k + 1 >= times.len() { break; }
+
+            let (t, u) = (times[k], times[k + 1]);
+            if plan[u][agent] == plan[t][agent].inverse() {
+                let edits = vec!((t, Action::NoOp), (u, Action::NoOp));
+                if try_edit(level, plan, agent, &edits) {
+                    k = k.saturating_sub(1);
+                    continue;
+                }
+            }
+            k += 1;
+        }
+    }
+}
+
+// runs of moves longer than the distance between their ends
+fn shorten_detours(level: &Level, start: &State, tables: &[Distances], plan: &mut Vec<JointAction>) {
+    for agent in 0..start.nb_agents() {
+        let mut k = 0;
+        loop {
+            let times = active(plan, agent);
+            if k >= times.len() { break; }
+
+            let is_move = |t: usize| matches!(plan[t][agent], Action::Move(_));
+            let mut m = k;
+            while m < times.len() && is_move(times[m]) { m += 1; }
+            if m == k { k += 1; continue; }
+
+            let states = states(start, plan, times[m - 1] + 1);
+            let from = states[times[k]].agent(agent);
+            let to = states[times[m - 1] + 1].agent(agent);
+            let path = shortest_moves(tables, &states, &times[k..m], from, to);
+
+            match path {
+                Some(ref moves) if moves.len() < m - k => {
+                    let edits = (k..m).map(|i| (times[i], moves.get(i - k).cloned().unwrap_or(Action::NoOp))).collect::<Vec<_>>();
+                    if !try_edit(level, plan, agent, &edits) { k = m; }
+                }
+                _ => k = m
+            }
+        }
+    }
+}
+
+// joint actions where every agent waits
+fn drop_noops(level: &Level, plan: &mut Vec<JointAction>) {
+    let kept = plan.iter().filter(|j| !j.is_noop()).cloned().collect::<Vec<JointAction>>();
+    if kept.len() < plan.len() && is_valid(level, &kept) { *plan = kept; }
+}
+
+/// Moves from `from` to `to` along a shortest path, taking the next cell free at each of
+/// `times` in `states`. None if `to` is out of reach or there are too few times.
+fn shortest_moves(tables: &[Distances], states: &[State], times: &[usize], from: Pos, to: Pos) -> Option<Vec<Action>> {
+    let table = tables.iter().find(|t| t.contains(from) && t.contains(to))?;
+    let mut moves = Vec::new();
+    let mut cell = from;
+
+    while cell != to {
+        let t = *times.get(moves.len())?;
+        let left = table.between(cell, to)?;
+        let next = DIRS.iter().cloned()
+            .find(|&d| table.between(cell + d, to) == Some(left - 1) && (cell + d == to || states[t].is_free(cell + d)))?;
+        moves.push(Action::Move(next));
+        cell += next;
+    }
+    Some(moves)
+}
+
+// applies `edits` to the actions of `agent` if the plan stays valid
+fn try_edit(level: &Level, plan: &mut Vec<JointAction>, agent: usize, edits: &[(usize, Action)]) -> bool {
+    let mut edited = plan.clone();
+    for &(t, action) in edits {
+        let mut actions = edited[t].actions().to_vec();
+        actions[agent] = action;
+        edited[t] = JointAction::new(actions);
+    }
+    if !is_valid(level, &edited) { return false; }
+    *plan = edited;
+    true
+}
+
+// times at which `agent` does something
+fn active(plan: &[JointAction], agent: usize) -> Vec<usize> {
+    (0..plan.len()).filter(|&t| plan[t][agent] != Action::NoOp).collect()
+}
+
+// states before each of the first `length` joint actions, and after the last
+fn states(start: &State, plan: &[JointAction], length: usize) -> Vec<State> {
+    let mut states = vec!(start.clone());
+    for joint in &plan[..length] {
+        let mut next = states.last().unwrap().clone();
+        joint.execute(&mut next);
+        states.push(next);
+    }
+    states
+}
+
+fn nb_actions(plan: &[JointAction]) -> usize {
+    plan.iter().map(|j| j.actions().iter().filter(|&&a| a != Action::NoOp).count()).sum()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use defs::dir::{NORTH, EAST, SOUTH, WEST};
+
+    fn joint(actions: &[Action]) -> JointAction { JointAction::new(actions.to_vec()) }
+
+    #[test]
+    fn detours_and_returns() {
+        let level = Level::new("red: 0, A\n+++++++\n+0A  a+\n+     +\n+++++++\n");
+        let push = Action::Push(EAST, EAST);
+        let plan = vec!(
+            joint(&[Action::Move(SOUTH)]), joint(&[Action::Move(NORTH)]),       // there and back
+            joint(&[push]), joint(&[Action::NoOp]), joint(&[push]),
+            joint(&[Action::Move(SOUTH)]), joint(&[Action::Move(WEST)]),       // a walk around
+            joint(&[Action::Move(NORTH)]), joint(&[Action::Move(EAST)]),       // the agent's cell
+            joint(&[push]), joint(&[Action::NoOp])
+        );
+        assert!(is_valid(&level, &plan));
+
+        let optimized = optimize(&level, &plan);
+        assert!(is_valid(&level, &optimized));
+        assert_eq!(optimized, vec!(joint(&[push]), joint(&[push]), joint(&[push])));
+
+        // left alone when it does not solve the level
+        assert_eq!(optimize(&level, &plan[..2]), plan[..2].to_vec());
+    }
+}
@@ -0,0 +1,94 @@
+use defs::dir::DIRS;
+use defs::grid::Grid;
+use defs::pos::Pos;
+use level::component::Component;
+use level::dead::DeadSquares;
+use level::distance::Distances;
+use level::level::Level;
+use level::order::articulation_points;
+use state::state::State;
+
+/// Where boxes and agents can wait out of the way. A cell is never a parking spot if it is an
+/// articulation point of its component, in a corridor (walls on two opposite sides) or an
+/// unfilled goal, since an object left there cuts the level or gets in the way later.
+pub struct Parking {
+    excluded: Grid<bool>,       // cells never worth parking on, goals aside
+    dead: DeadSquares,
+    tables: Vec<Distances>
+}
+
+impl Parking {
+    pub fn new(level: &Level) -> Parking {
+        let (rows, cols) = level.size();
+        let mut excluded = Grid::<bool>::new(rows, cols);
+
+        for comp in Component::all(level) {
+            for pos in articulation_points(&comp) { excluded[pos] = true; }
+            for i in 0..comp.nb_free_cells() {
+                let pos = comp.pos_of(i as i16);
+                if is_corridor(&comp, pos) { excluded[pos] = true; }
+            }
+        }
+
+        Parking { excluded: excluded, dead: DeadSquares::new(level), tables: Distances::all(level) }
+    }
+
+    /// Parking spots for the object on `from` in `state`, closest first, with their distance.
+    /// The cells of `keep` and the goals not filled in `state` are left clear, and boxes are
+    /// never parked where they could not reach a goal again. Distances ignore agents and boxes,
+    /// and only free cells are returned.
+    pub fn spots(&self, level: &Level, state: &State, from: Pos, keep: &[Pos]) -> Vec<(Pos, u32)> {
+        let table = match self.tables.iter().find(|t| t.contains(from)) {
+            Some(table) => table,
+            None => return Vec::new()
+        };
+        let comp = table.component();
+        let item = state[from];
+
+        let mut spots = Vec::new();
+        for i in 0..comp.nb_free_cells() {
+            let pos = comp.pos_of(i as i16);
+            if self.excluded[pos] || keep.contains(&pos) || !state.is_free(pos) { continue; }
+            if level.goals().iter().any(|g| g.pos == pos && !state.satisfies(g)) { continue; }
+            if self.dead.is_dead(item, pos) { continue; }
+
+            if let Some(d) = table.between(from, pos) { spots.push((pos, d as u32)); }
+        }
+
+        spots.sort_by_key(|&(p, d)| (d, p.row, p.col));
+        spots
+    }
+
+    /// Whether `pos` is never a parking spot, whatever the state.
+    pub fn is_excluded(&self, pos: Pos) -> bool { self.excluded[pos] }
+}
+
+// free neighbours on both sides along one axis only
+fn is_corridor(comp: &Component, pos: Pos) -> bool {
+    let open = |i: usize| comp.contains(pos + DIRS[i]);
+    (open(0) && open(2) && !open(1) && !open(3)) || (open(1) && open(3) && !open(0) && !open(2))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn room_off_corridor() {
+        // the agent parks in the room below the corridor
+        let level = Level::new("red: 0, A\n+++++++\n+0A  a+\n+++ +++\n++   ++\n++   ++\n+++++++\n");
+        let state = State::new(&level);
+        let parking = Parking::new(&level);
+
+        // the entrance of the room cuts the level, the corridor cells are excluded
+        assert!(parking.is_excluded(Pos::new(2, 3)));
+        assert!(parking.is_excluded(Pos::new(1, 4)));
+
+        let spots = parking.spots(&level, &state, Pos::new(1, 1), &[Pos::new(3, 3)]);
+        let cells = spots.iter().map(|&(p, _)| p).collect::<Vec<Pos>>();
+        assert_eq!(cells[0], Pos::new(3, 2));
+        assert!(!cells.contains(&Pos::new(3, 3)));
+        assert!(!cells.contains(&Pos::new(1, 5)));
+        assert!(spots.windows(2).all(|w| w[0].1 <= w[1].1));
+    }
+}
@@ -0,0 +1,287 @@
+use std::fs::File;
+use std::io::{Read, Write};
+
+use defs::pos::Pos;
+use level::item::{Item, Color};
+use level::level::Level;
+use state::action::Action;
+use state::state::State;
+use super::graph::bfs;
+use super::problem::Problem;
+use super::stats::{Limits, Termination};
+
+/// Window shapes (rows, cols) of the database, smallest first.
+pub const SHAPES: [(usize, usize); 4] = [(2, 2), (2, 3), (3, 2), (3, 3)];
+
+/// Largest number of cells of a window shape.
+pub const MAX_CELLS: usize = 9;
+
+const MAGIC: &[u8] = b"PDB1";
+
+// free cells around a window during the local search, walls beyond
+const MARGIN: usize = 2;
+
+/// Digits of a window cell in the base 3 code of a pattern.
+pub const EMPTY: usize = 0;
+pub const WALL: usize = 1;
+pub const BOX: usize = 2;
+
+/// Deadlock patterns: small windows of walls and boxes from which no box can ever leave.
+///
+/// A window is coded in base 3, cell by cell in row major order (`EMPTY`, `WALL`, `BOX`, an
+/// agent counting as empty), and each shape keeps one bit per code. A pattern is proved
+/// deadlocked by an exhaustive search where everything around the window is free, the
+/// agent may start anywhere and can move every box: if even then no box can be brought out
+/// of the window, no box of the window will ever reach a goal outside of it.
+pub struct PatternDb {
+    shapes: Vec<Shape>
+}
+
+struct Shape {
+    rows: usize,
+    cols: usize,
+    bits: Vec<u8>
+}
+
+impl Shape {
+    fn new(rows: usize, cols: usize) -> Shape {
+        Shape { rows: rows, cols: cols, bits: vec!(0; Shape::nb_bytes(rows, cols)) }
+    }
+
+    // one bit per code, `rows * cols` must not exceed `MAX_CELLS`
+    fn nb_bytes(rows: usize, cols: usize) -> usize { 3usize.pow((rows * cols) as u32).div_ceil(8) }
+
+    fn get(&self, code: usize) -> bool { self.bits[code / 8] & (1 << (code % 8)) != 0 }
+    fn set(&mut self, code: usize) { self.bits[code / 8] |= 1 << (code % 8); }
+}
+
+impl PatternDb {
+    /// Enumerates every pattern of `shapes`, given smallest first. Patterns containing a
+    /// smaller deadlocked pattern are not stored, matching the smaller one is enough. Each
+    /// local search may expand `limit` nodes, a pattern is only stored if its search was
+    /// exhaustive.
+    pub fn build(shapes: &[(usize, usize)], limit: u64) -> PatternDb {
+        let mut db = PatternDb { shapes: Vec::new() };
+
+        for &(rows, cols) in shapes {
+            let mut shape = Shape::new(rows, cols);
+
+            for code in 0..3usize.pow((rows * cols) as u32) {
+                let cells = decode(code, rows * cols);
+                if !cells.contains(&BOX) { continue; }
+                if db.contains_smaller(&cells, rows, cols) { continue; }
+                if is_deadlocked(&cells, rows, cols, limit) { shape.set(code); }
+            }
+
+            db.shapes.push(shape);
+        }
+
+        db
+    }
+
+    /// Number of stored patterns.
+    pub fn len(&self) -> usize {
+        self.shapes.iter().map(|s| s.bits.iter().map(|b| b.count_ones() as usize).sum::<usize>()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool { self.shapes.iter().all(|s| s.bits.iter().all(|&b| b == 0)) }
+
+    pub fn shapes(&self) -> Vec<(usize, usize)> {
+        self.shapes.iter().map(|s| (s.rows, s.cols)).collect()
+    }
+
+    pub fn contains(&self, rows: usize, cols: usize, code: usize) -> bool {
+        self.shapes.iter().any(|s| s.rows == rows && s.cols == cols && s.get(code))
+    }
+
+    fn contains_smaller(&self, cells: &[usize], rows: usize, cols: usize) -> bool {
+        for s in &self.shapes {
+            if s.rows > rows || s.cols > cols { continue; }
+            for r0 in 0..(rows - s.rows + 1) {
+                for c0 in 0..(cols - s.cols + 1) {
+                    let mut code = 0;
+                    for r in (0..s.rows).rev() {
+                        for c in (0..s.cols).rev() { code = code * 3 + cells[(r0 + r) * cols + c0 + c]; }
+                    }
+                    if s.get(code) { return true; }
+                }
+            }
+        }
+        false
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), &'static str> {
+        let mut data = MAGIC.to_vec();
+        data.push(self.shapes.len() as u8);
+        for s in &self.shapes {
+            data.push(s.rows as u8);
+            data.push(s.cols as u8);
+            data.extend_from_slice(&s.bits);
+        }
+
+        File::create(path).and_then(|mut f| f.write_all(&data)).map_err(|_| "cannot write file")
+    }
+
+    pub fn load(path: &str) -> Result<PatternDb, &'static str> {
+        let mut data = Vec::new();
+        File::open(path).and_then(|mut f| f.read_to_end(&mut data)).map_err(|_| "cannot read file")?;
+
+        if data.len() < 5 || &data[..4] != MAGIC { return Err("not a pattern database"); }
+
+        let mut shapes = Vec::new();
+        let mut i = 5;
+        for _ in 0..data[4] {
+            if i + 2 > data.len() { return Err("truncated pattern database"); }
+            let (rows, cols) = (data[i] as usize, data[i + 1] as usize);
+            if rows == 0 || cols == 0 || rows * cols > MAX_CELLS { return Err("unsupported pattern shape"); }
+            let len = Shape::nb_bytes(rows, cols);
+            i += 2;
+
+            if len > data.len() - i { return Err("truncated pattern database"); }
+            let mut shape = Shape::new(rows, cols);
+            shape.bits.copy_from_slice(&data[i..i + len]);
+            shapes.push(shape);
+            i += len;
+        }
+
+        Ok(PatternDb { shapes: shapes })
+    }
+}
+
+fn decode(mut code: usize, n: usize) -> Vec<usize> {
+    (0..n).map(|_| { let d = code % 3; code /= 3; d }).collect()
+}
+
+/// Code of the window of `state` with top left corner `corner`, None if it goes out of the grid.
+pub fn encode(state: &State, corner: Pos, rows: usize, cols: usize) -> Option<usize> {
+    let mut code = 0;
+    for r in (0..rows).rev() {
+        for c in (0..cols).rev() {
+            let pos = corner + Pos::new(r as i8, c as i8);
+            if !state.in_bounds(pos) { return None; }
+            let item = state[pos];
+            let digit = if item.is_wall() { WALL } else if item.is_box() { BOX } else { EMPTY };
+            code = code * 3 + digit;
+        }
+    }
+    Some(code)
+}
+
+fn is_deadlocked(cells: &[usize], rows: usize, cols: usize, limit: u64) -> bool {
+    let off = MARGIN + 1;
+    let (arena_rows, arena_cols) = (rows + 2 * off, cols + 2 * off);
+
+    let mut text = String::new();
+    for r in 0..arena_rows {
+        for c in 0..arena_cols {
+            let border = r == 0 || c == 0 || r == arena_rows - 1 || c == arena_cols - 1;
+            text.push(if border { '+' } else { ' ' });
+        }
+        text.push('\n');
+    }
+
+    let mut empty = State::new(&Level::new(&text));
+    for (i, &cell) in cells.iter().enumerate() {
+        let pos = Pos::new((off + i / cols) as i8, (off + i % cols) as i8);
+        match cell {
+            WALL => empty.place(pos, Item::wall()),
+            BOX  => empty.place(pos, Item::new(b'A', Color::Blue)),
+            _    => {}
+        }
+    }
+
+    let mut starts = Vec::new();
+    for r in 1..(arena_rows - 1) {
+        for c in 1..(arena_cols - 1) {
+            let pos = Pos::new(r as i8, c as i8);
+            if !empty.is_free(pos) { continue; }
+            let mut start = empty.clone();
+            start.place(pos, Item::new(b'0', Color::Blue));
+            starts.push(start);
+        }
+    }
+
+    let problem = WindowProblem { starts: starts, corner: Pos::new(off as i8, off as i8), rows: rows, cols: cols };
+    let outcome = bfs(&problem, &Limits::new().max_expanded(limit));
+    outcome.stats.termination == Some(Termination::Exhausted)
+}
+
+/// Can a box be brought out of the window?
+struct WindowProblem {
+    starts: Vec<State>,
+    corner: Pos,
+    rows: usize,
+    cols: usize
+}
+
+impl Problem for WindowProblem {
+    type Node = State;
+    type Action = Action;
+    type Key = State;
+
+    fn initial(&self) -> Vec<State> { self.starts.clone() }
+
+    fn is_goal(&self, state: &State) -> bool {
+        let (rows, cols) = state.size();
+        for r in 0..rows {
+            for c in 0..cols {
+                let pos = Pos::new(r as i8, c as i8);
+                let d = pos - self.corner;
+                let inside = d.row >= 0 && d.col >= 0 && (d.row as usize) < self.rows && (d.col as usize) < self.cols;
+                if state[pos].is_box() && !inside { return true; }
+            }
+        }
+        false
+    }
+
+    fn expand(&self, state: &State, out: &mut Vec<(Action, State, u32)>) {
+        for action in Action::all() {
+            if action == Action::NoOp { continue; }
+            if let Some(next) = state.apply(0, action) { out.push((action, next, 1)); }
+        }
+    }
+
+    fn key(&self, state: &State) -> State { state.clone() }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn code(cells: &[usize]) -> usize {
+        cells.iter().rev().fold(0, |code, &d| code * 3 + d)
+    }
+
+    #[test]
+    fn local_search() {
+        // boxes in a dead end can be pulled out, not a box sealed in walls
+        assert!(!is_deadlocked(&[WALL, BOX, WALL,
+                                 WALL, BOX, WALL,
+                                 WALL, WALL, WALL], 3, 3, 100_000));
+        assert!(is_deadlocked(&[WALL, WALL, WALL,
+                                WALL, BOX,  WALL,
+                                WALL, WALL, WALL], 3, 3, 100_000));
+        assert!(!is_deadlocked(&[BOX, BOX, BOX, BOX], 2, 2, 100_000));
+    }
+
+    #[test]
+    fn build_save_load() {
+        let db = PatternDb::build(&SHAPES[..1], 10_000);
+        let path = ::std::env::temp_dir().join("aisolver_test.pdb");
+        let path = path.to_str().unwrap();
+
+        db.save(path).unwrap();
+        let loaded = PatternDb::load(path).unwrap();
+        assert_eq!(loaded.len(), db.len());
+        assert_eq!(loaded.shapes(), vec!((2, 2)));
+        assert!(!loaded.contains(2, 2, code(&[BOX, BOX, BOX, BOX])));
+        assert!(PatternDb::load("levels/easy.lvl").is_err());
+
+        // shapes too large, and files shorter than their shapes, are rejected
+        let two_by_two = b"PDB1\x02\x02\x02\0\0\0\0\0\0\0\0\0\0\0";    // second shape missing
+        for data in &[&b"PDB1\x01\xff\xff"[..], &b"PDB1\x01\x03\x04"[..], &two_by_two[..]] {
+            File::create(path).and_then(|mut f| f.write_all(data)).unwrap();
+            assert!(PatternDb::load(path).is_err());
+        }
+    }
+}
@@ -0,0 +1,100 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::channel;
+use std::thread;
+
+use level::level::Level;
+use super::deadlock::DeadlockTables;
+use super::graph::Solution;
+use super::matching::Matching;
+use super::patterns::PatternDb;
+use super::problem::{LevelProblem, Step};
+use super::stats::{Limits, SearchStats};
+use super::strategy::Strategy;
+
+/// Strategies run by `portfolio` unless told otherwise.
+pub const DEFAULT: [Strategy; 6] = [
+    Strategy::Greedy,
+    Strategy::WeightedAStar(5.0),
+    Strategy::WeightedAStar(2.0),
+    Strategy::WeightedAStar(1.5),
+    Strategy::IdaStar,
+    Strategy::Reverse
+];
+
+/// When a portfolio stops.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Policy {
+    First,      // with the first plan found, the other searches are cancelled
+    Best        // once every search stopped, keeping the cheapest plan
+}
+
+/// Result of a portfolio: the winning strategy and its plan, and the statistics of every run
+/// in the order they stopped.
+pub struct Portfolio {
+    pub best: Option<(Strategy, Solution<Step>)>,
+    pub runs: Vec<(Strategy, SearchStats)>
+}
+
+/// Runs `strategies` concurrently, one thread each, under the same `limits`. The level, the
+/// heuristic and deadlock tables and the pattern database are built once and shared read-only;
+/// each thread only has its own `LevelProblem` around them, since corral caches are per
+/// search. Searches are stopped cooperatively through `limits.cancel`, which is replaced by a
+/// flag of the portfolio.
+pub fn portfolio(level: Arc<Level>, patterns: Option<Arc<PatternDb>>, strategies: &[Strategy],
+                 policy: Policy, limits: &Limits) -> Portfolio {
+    let cancel = Arc::new(AtomicBool::new(false));
+    let limits = limits.clone().cancel(cancel.clone());
+    let heuristic = Arc::new(Matching::new(&level));
+    let tables = Arc::new(DeadlockTables::new(&level));
+    let (sender, receiver) = channel();
+
+    let threads = strategies.iter().enumerate().map(|(i, &strategy)| {
+        let (level, patterns, heuristic, tables) = (level.clone(), patterns.clone(), heuristic.clone(), tables.clone());
+        let (limits, sender) = (limits.clone(), sender.clone());
+
+        thread::spawn(move || {
+            let mut problem = LevelProblem::with_tables(&level, tables);
+            if let Some(ref db) = patterns { problem.set_patterns(db); }
+            let outcome = strategy.run_with(&problem, &*heuristic, &limits);
+            let _ = sender.send((i, outcome));
+        })
+    }).collect::<Vec<_>>();
+    drop(sender);
+
+    let mut best: Option<(Strategy, Solution<Step>)> = None;
+    let mut runs = Vec::new();
+
+    for (i, outcome) in receiver {
+        if let Some(solution) = outcome.solution {
+            if best.as_ref().is_none_or(|b| solution.cost < b.1.cost) { best = Some((strategies[i], solution)); }
+            if policy == Policy::First { cancel.store(true, Ordering::Relaxed); }
+        }
+        runs.push((strategies[i], outcome.stats));
+    }
+
+    for thread in threads { let _ = thread.join(); }
+    Portfolio { best: best, runs: runs }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use search::graph::bfs;
+    use search::stats::Termination;
+
+    #[test]
+    fn policies() {
+        let level = Arc::new(Level::new("++++++++\n+0 A  a+\n+ ++++ +\n+  B  b+\n++++++++\n"));
+        let optimal = bfs(&LevelProblem::new(&level), &Limits::new()).solution.unwrap().cost;
+
+        let result = portfolio(level.clone(), None, &DEFAULT, Policy::First, &Limits::new());
+        assert!(result.best.is_some());
+        assert_eq!(result.runs.len(), DEFAULT.len());
+
+        let strategies = [Strategy::Greedy, Strategy::AStar];
+        let result = portfolio(level.clone(), None, &strategies, Policy::Best, &Limits::new());
+        assert_eq!(result.best.unwrap().1.cost, optimal);
+        assert!(result.runs.iter().all(|r| r.1.termination == Some(Termination::Solved)));
+    }
+}
@@ -0,0 +1,152 @@
+use std::cmp::Reverse;
+use std::collections::HashSet;
+use std::mem::size_of;
+
+use level::distance::Distances;
+use level::level::Level;
+use state::action::Action;
+use state::joint::JointAction;
+use state::state::State;
+use super::allocation::{Allocation, Tasks};
+use super::agent::{AgentTask, LOW_LEVEL_LIMIT};
+use super::cbs::{cbs, joint_plan, Objective};
+use super::graph::Solution;
+use super::optimize::is_valid;
+use super::reservation::{Reservations, FOREVER};
+use super::stats::{Limits, Monitor, Outcome, Termination};
+
+/// Prioritized planning: the agents plan one after the other, each avoiding the cells that the
+/// plans before it reserve, for the agents and their boxes. Agents not planned yet stay where
+/// they start.
+///
+/// `order` gives the priorities, highest first; ids of no agent are ignored and the agents it
+/// leaves out come last. By default the agents with the most goals go first. When an agent
+/// finds no plan, or is the first to fail when the plans run together, it moves to the front,
+/// or to the back if it was already first, and everything is planned again until an order
+/// comes back. Neither complete nor optimal, but cheap: the cost of the joint plan returned is
+/// its length.
+pub fn prioritized(level: &Level, order: Option<&[usize]>, limits: &Limits) -> Outcome<Solution<JointAction>> {
+    let start = State::new(level);
+    let tables = Distances::all(level);
+    let allocation = Allocation::new(&start, &tables, &Tasks::hungarian(level, &start, &tables));
+    let mut monitor = Monitor::new(limits);
+
+    let mut low_limits = limits.clone().max_expanded(LOW_LEVEL_LIMIT);
+    low_limits.progress = None;
+
+    if allocation.goals.iter().any(|g| g.is_none()) {
+        return monitor.finish(Termination::Exhausted, None);
+    }
+
+    let agents = AgentTask::all(level, &start, &allocation);
+    let mut order = match order {
+        Some(order) => {
+            let mut valid = Vec::new();
+            for a in order.iter().cloned().chain(0..agents.len()) {
+                if a < agents.len() && agents[a].is_some() && !valid.contains(&a) { valid.push(a); }
+            }
+            valid
+        }
+        None => {
+            let mut order = (0..agents.len()).filter(|&a| agents[a].is_some()).collect::<Vec<usize>>();
+            let nb_goals = |a: usize| agents[a].as_ref().map_or(0, |t| t.goals.len());
+            order.sort_by_key(|&a| Reverse(nb_goals(a)));
+            order
+        }
+    };
+    let mut tried = HashSet::new();
+
+    while tried.insert(order.clone()) {
+        let mut reservations = Reservations::new();
+        for &a in &order {
+            let agent = agents[a].as_ref().unwrap();
+            for cell in agent.objects(&agent.world) { reservations.reserve(cell, 0, FOREVER, a); }
+        }
+
+        let mut plans = vec!(Vec::<Action>::new(); start.nb_agents());
+        let mut failed = None;
+        for &a in &order {
+            let memory = reservations.mem_size() + tried.len() * order.len() * size_of::<usize>()
+                + plans.iter().map(|p| p.len() * size_of::<Action>()).sum::<usize>();
+            if let Some(t) = monitor.expand(order.len(), 0, memory) { return monitor.finish(t, None); }
+
+            let agent = agents[a].as_ref().unwrap();
+            reservations.release(a);
+            match agent.plan(&[], Some(&reservations), &tables, &low_limits) {
+                Ok(plan) => {
+                    reservations.reserve_plan(&agent.world, a, &plan);
+                    plans[a] = plan;
+                    monitor.generated(1);
+                }
+                Err(_) => { failed = Some(a); break; }
+            }
+        }
+
+        let a = match failed {
+            Some(a) => a,
+            None => {
+                let plan = joint_plan(&plans, start.nb_agents());
+                if is_valid(level, &plan) {
+                    let solution = Solution { cost: plan.len() as u32, plan: plan };
+                    return monitor.finish(Termination::Solved, Some(solution));
+                }
+                match first_failure(&start, &plan).or(order.first().cloned()) {
+                    Some(a) => a,
+                    None => break
+                }
+            }
+        };
+
+        // the first agent can only fail because of agents still waiting on their start
+        if order[0] == a {
+            order.rotate_left(1);
+        } else {
+            order.retain(|&b| b != a);
+            order.insert(0, a);
+        }
+    }
+
+    monitor.finish(Termination::Exhausted, None)
+}
+
+// the agent of the first action failing when `plan` runs from `start`
+fn first_failure(start: &State, plan: &[JointAction]) -> Option<usize> {
+    let mut state = start.clone();
+    plan.iter().filter_map(|joint| joint.execute(&mut state).iter().position(|&ok| !ok)).next()
+}
+
+/// Prioritized planning first, then Conflict-Based Search for `objective` if it fails.
+pub fn prioritized_or_cbs(level: &Level, objective: Objective, limits: &Limits) -> Outcome<Solution<JointAction>> {
+    let outcome = prioritized(level, None, limits);
+    if outcome.solution.is_some() || outcome.stats.termination != Some(Termination::Exhausted) { return outcome; }
+    cbs(level, objective, limits)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn crossing_boxes() {
+        let level = Level::new("red: 0, A\nblue: 1, B\n+++++++++\n+0A   B1+\n+  b a  +\n+       +\n+++++++++\n");
+        let solution = prioritized(&level, None, &Limits::new()).solution.expect("no plan");
+        assert!(is_valid(&level, &solution.plan));
+        assert_eq!(solution.cost as usize, solution.plan.len());
+    }
+
+    #[test]
+    fn reordering() {
+        // agent 1 blocks the corridor until it pushes its box, so it has to plan first
+        let level = Level::new("red: 0, A\nblue: 1, B\n+++++++\n+0A 1a+\n++++B++\n++++b++\n+++++++\n");
+        let limits = Limits::new();
+        let solution = prioritized(&level, Some(&[0, 1]), &limits).solution.expect("no plan");
+        assert!(is_valid(&level, &solution.plan));
+
+        // unknown and repeated ids are ignored, agent 1 is added
+        let solution = prioritized(&level, Some(&[0, 7, 0]), &limits).solution.expect("no plan");
+        assert!(is_valid(&level, &solution.plan));
+
+        let level = Level::new("red: 0, A\nblue: 1\n+++++++\n+0A 1a+\n+++++++\n");
+        assert!(prioritized_or_cbs(&level, Objective::SumOfCosts, &Limits::new().max_expanded(50)).solution.is_none());
+    }
+}
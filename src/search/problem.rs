@@ -0,0 +1,130 @@
+use std::hash::Hash;
+use std::mem::size_of;
+use std::sync::Arc;
+
+use level::level::Level;
+use state::action::Action;
+use state::packed::{Encoder, PackedState};
+use state::state::State;
+use super::deadlock::{DeadlockTables, Deadlocks};
+use super::patterns::PatternDb;
+
+/// A search space: start nodes, successor function and goal test.
+pub trait Problem {
+    type Node: Clone + Eq + Hash;
+    type Action: Clone;
+    /// What closed sets store to recognize a node, often the node itself.
+    type Key: Eq + Hash;
+
+    fn initial(&self) -> Vec<Self::Node>;
+    fn is_goal(&self, node: &Self::Node) -> bool;
+
+    /// Pushes the (action, successor, cost) triples of `node` on `out`.
+    fn expand(&self, node: &Self::Node, out: &mut Vec<(Self::Action, Self::Node, u32)>);
+
+    /// Approximate number of bytes used by a node, heap included.
+    fn node_size(&self, _node: &Self::Node) -> usize { size_of::<Self::Node>() }
+
+    fn key(&self, node: &Self::Node) -> Self::Key;
+
+    /// Approximate number of bytes used by a key, heap included.
+    fn key_size(&self, _key: &Self::Key) -> usize { size_of::<Self::Key>() }
+}
+
+/// One agent acting while all the others wait.
+pub type Step = (usize, Action);
+
+/// Sequential formulation of a level: every step moves exactly one agent. Moves bringing a
+/// box onto one of its dead squares, or into a freeze or corral deadlock, are not generated,
+/// and a start already in such a deadlock is not searched at all.
+pub struct LevelProblem<'a> {
+    level: &'a Level,
+    start: State,
+    actions: Vec<Action>,
+    deadlocks: Deadlocks<'a>,
+    encoder: Encoder        // packs the states kept in closed sets
+}
+
+impl<'a> LevelProblem<'a> {
+    pub fn new(level: &'a Level) -> LevelProblem<'a> {
+        Self::from_state(level, State::new(level))
+    }
+
+    pub fn from_state(level: &'a Level, start: State) -> LevelProblem<'a> {
+        Self::with_deadlocks(level, start, Deadlocks::new(level))
+    }
+
+    /// The level from its start, with deadlock tables built beforehand for it.
+    pub fn with_tables(level: &'a Level, tables: Arc<DeadlockTables>) -> LevelProblem<'a> {
+        Self::with_deadlocks(level, State::new(level), Deadlocks::with_tables(level, tables))
+    }
+
+    fn with_deadlocks(level: &'a Level, start: State, deadlocks: Deadlocks<'a>) -> LevelProblem<'a> {
+        let actions = Action::all().into_iter().filter(|&a| a != Action::NoOp).collect();
+        LevelProblem {
+            level: level,
+            start: start,
+            actions: actions,
+            deadlocks: deadlocks,
+            encoder: Encoder::new(level)
+        }
+    }
+
+    pub fn level(&self) -> &'a Level { self.level }
+    pub fn start(&self) -> &State { &self.start }
+    pub fn actions(&self) -> &[Action] { &self.actions }
+
+    /// Also prunes states matching deadlock patterns of `db`.
+    pub fn set_patterns(&mut self, db: &'a PatternDb) { self.deadlocks.set_patterns(db); }
+
+    /// Whether `action` moves a box onto a dead square.
+    pub fn is_pruned(&self, state: &State, agent: usize, action: Action) -> bool {
+        match action.effect(state.agent(agent)).box_move {
+            Some((from, to)) => {
+                state.in_bounds(from) && state.in_bounds(to) && self.deadlocks.dead_squares().is_dead(state[from], to)
+            }
+            None => false
+        }
+    }
+
+    /// Whether `next`, the state reached by `action` of `agent`, is a detected deadlock.
+    pub fn is_deadlocked(&self, next: &State, agent: usize, action: Action) -> bool {
+        let pos = next.agent(agent);
+        match action {
+            Action::Push(_, b) => self.deadlocks.after_move(next, pos + b),
+            Action::Pull(a, _) => self.deadlocks.after_move(next, pos - a),
+            _ => false
+        }
+    }
+}
+
+impl<'a> Problem for LevelProblem<'a> {
+    type Node = State;
+    type Action = Step;
+    type Key = PackedState;
+
+    // nothing to search from a start already deadlocked
+    fn initial(&self) -> Vec<State> {
+        if self.deadlocks.is_deadlocked(&self.start) { Vec::new() } else { vec!(self.start.clone()) }
+    }
+
+    fn is_goal(&self, state: &State) -> bool { state.is_goal_state(self.level) }
+
+    fn expand(&self, state: &State, out: &mut Vec<(Step, State, u32)>) {
+        for agent in 0..state.nb_agents() {
+            for &action in &self.actions {
+                if self.is_pruned(state, agent, action) { continue; }
+                if let Some(next) = state.apply(agent, action) {
+                    if self.is_deadlocked(&next, agent, action) { continue; }
+                    out.push(((agent, action), next, 1));
+                }
+            }
+        }
+    }
+
+    fn node_size(&self, state: &State) -> usize { state.mem_size() }
+
+    fn key(&self, state: &State) -> PackedState { self.encoder.pack(state) }
+
+    fn key_size(&self, key: &PackedState) -> usize { key.mem_size() }
+}
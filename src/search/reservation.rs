@@ -0,0 +1,134 @@
+use std::collections::HashMap;
+use std::mem::size_of;
+
+use defs::pos::Pos;
+use state::action::Action;
+use state::state::State;
+
+/// End of a reservation that is never released.
+pub const FOREVER: u32 = u32::MAX;
+
+/// Space-time reservation table: which agent holds each cell over which times, for itself
+/// or one of its boxes. Times count joint actions like the planner constraints, time t being
+/// the state before the t-th one.
+#[derive(Debug, Clone, Default)]
+pub struct Reservations {
+    cells: HashMap<Pos, Vec<(u32, u32, usize)>>,    // inclusive time intervals and holders
+    horizon: u32
+}
+
+impl Reservations {
+    pub fn new() -> Reservations {
+        Reservations::default()
+    }
+
+    /// Approximate number of bytes used by the table.
+    pub fn mem_size(&self) -> usize {
+        size_of::<Reservations>() + self.cells.values()
+            .map(|v| size_of::<(Pos, Vec<(u32, u32, usize)>)>() + v.len() * size_of::<(u32, u32, usize)>())
+            .sum::<usize>()
+    }
+
+    /// Holds `cell` for `agent` from time `from` to `to`, both included.
+    pub fn reserve(&mut self, cell: Pos, from: u32, to: u32, agent: usize) {
+        self.cells.entry(cell).or_default().push((from, to, agent));
+        self.horizon = self.horizon.max(if to == FOREVER { from } else { to });
+    }
+
+    /// Reserves the cells used by `agent` and the boxes of its color while running `plan`
+    /// from `world`. Each object keeps the cell it ends on forever.
+    pub fn reserve_plan(&mut self, world: &State, agent: usize, plan: &[Action]) {
+        let color = world[world.agent(agent)].color();
+        let (rows, cols) = world.size();
+
+        // current cell of each object, and since when it is there
+        let mut objects = vec!((world.agent(agent), 0));
+        for row in 0..rows {
+            for col in 0..cols {
+                let pos = Pos::new(row as i8, col as i8);
+                if world[pos].is_box() && world[pos].color() == color { objects.push((pos, 0)); }
+            }
+        }
+
+        let mut state = world.clone();
+        for (t, &action) in plan.iter().enumerate() {
+            let t = t as u32;
+            let effect = action.effect(state.agent(agent));
+            state = state.apply(agent, action).expect("plan not applicable");
+
+            let mut moves = vec!((effect.agent_from, effect.agent_to));
+            if let Some(m) = effect.box_move { moves.push(m); }
+            let mut moved = vec!(false; objects.len());
+            for &(src, dst) in moves.iter().filter(|&&(src, dst)| src != dst) {
+                if let Some(i) = (0..objects.len()).find(|&i| !moved[i] && objects[i].0 == src) {
+                    self.reserve(src, objects[i].1, t, agent);
+                    objects[i] = (dst, t + 1);
+                    moved[i] = true;
+                }
+            }
+        }
+
+        for (cell, since) in objects {
+            self.reserve(cell, since, FOREVER, agent);
+        }
+    }
+
+    /// Agent holding `cell` at `time`, if any.
+    pub fn holder(&self, cell: Pos, time: u32) -> Option<usize> {
+        self.cells.get(&cell)
+            .and_then(|v| v.iter().find(|&&(from, to, _)| from <= time && time <= to))
+            .map(|&(_, _, agent)| agent)
+    }
+
+    /// Whether `cell` is held by nobody but `agent` at `time`.
+    pub fn is_free(&self, cell: Pos, time: u32, agent: usize) -> bool {
+        self.cells.get(&cell).is_none_or(|v| {
+            v.iter().all(|&(from, to, holder)| holder == agent || time < from || time > to)
+        })
+    }
+
+    /// Last time at which a reservation starts or ends: the table does not change after it.
+    pub fn horizon(&self) -> u32 {
+        self.horizon
+    }
+
+    /// Drops every reservation of `agent`.
+    pub fn release(&mut self, agent: usize) {
+        for v in self.cells.values_mut() {
+            v.retain(|&(_, _, holder)| holder != agent);
+        }
+        self.cells.retain(|_, v| !v.is_empty());
+        self.horizon = self.cells.values().flat_map(|v| v.iter())
+            .map(|&(from, to, _)| if to == FOREVER { from } else { to }).max().unwrap_or(0);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use defs::dir::EAST;
+    use level::level::Level;
+
+    #[test]
+    fn plan_reservations() {
+        let level = Level::new("red: 0, A\n++++++\n+0A  +\n++++++\n");
+        let state = State::new(&level);
+        let mut table = Reservations::new();
+        table.reserve_plan(&state, 0, &[Action::Push(EAST, EAST), Action::NoOp, Action::Push(EAST, EAST)]);
+
+        // the box leaves (1, 2) after step 0, the agent follows it there until step 2
+        assert_eq!(table.holder(Pos::new(1, 1), 0), Some(0));
+        assert_eq!(table.holder(Pos::new(1, 1), 1), None);
+        assert_eq!(table.holder(Pos::new(1, 2), 2), Some(0));
+        assert_eq!(table.holder(Pos::new(1, 2), 3), None);
+        assert_eq!(table.holder(Pos::new(1, 4), 1000), Some(0));
+        assert!(table.is_free(Pos::new(1, 4), 1000, 0));
+        assert!(!table.is_free(Pos::new(1, 4), 3, 1));
+        assert!(table.is_free(Pos::new(1, 4), 2, 1));
+        assert_eq!(table.horizon(), 3);
+
+        table.release(0);
+        assert_eq!(table.holder(Pos::new(1, 4), 1000), None);
+        assert_eq!(table.horizon(), 0);
+    }
+}
@@ -0,0 +1,94 @@
+use std::collections::HashMap;
+
+use defs::pos::Pos;
+use state::action::Action;
+use state::joint::JointAction;
+use state::state::State;
+use super::problem::Step;
+
+/// Turns a sequential plan into joint actions, each step as early as the steps before it allow.
+///
+/// A step depends on the earlier steps of its agent and on those touching one of its cells:
+/// where its agent and box come from and go to. Steps of one joint action then touch disjoint
+/// cells, so each finds its cells as the sequential plan left them, and the server's rules
+/// against entering a cell being vacated never apply. The makespan is the longest chain of
+/// dependencies, the best possible without reordering steps on a cell.
+///
+/// The joint plan is checked with the executor against the sequential one; should they differ,
+/// the sequential plan is returned, one step per joint action. NoOps are dropped.
+pub fn parallelize(start: &State, plan: &[Step]) -> Vec<JointAction> {
+    let nb_agents = start.nb_agents();
+    let mut state = start.clone();
+    let mut agent_ready = vec!(0; nb_agents);
+    let mut cell_ready = HashMap::<Pos, usize>::new();
+    let mut layers = Vec::<Vec<Action>>::new();
+
+    for &(agent, action) in plan.iter().filter(|&&(_, a)| a != Action::NoOp) {
+        let effect = action.effect(state.agent(agent));
+        let mut cells = vec!(effect.agent_from, effect.agent_to);
+        if let Some((from, to)) = effect.box_move { cells.push(from); cells.push(to); }
+
+        let t = cells.iter().filter_map(|c| cell_ready.get(c)).fold(agent_ready[agent], |t, &r| t.max(r));
+        if t == layers.len() { layers.push(vec!(Action::NoOp; nb_agents)); }
+        layers[t][agent] = action;
+
+        agent_ready[agent] = t + 1;
+        for c in cells { cell_ready.insert(c, t + 1); }
+        state = match state.apply(agent, action) {
+            Some(next) => next,
+            None => return serial(nb_agents, plan)
+        };
+    }
+
+    let joint = layers.into_iter().map(JointAction::new).collect::<Vec<JointAction>>();
+    let mut check = start.clone();
+    for step in &joint {
+        if !step.execute(&mut check).iter().all(|&ok| ok) { return serial(nb_agents, plan); }
+    }
+    if check != state { return serial(nb_agents, plan); }
+    joint
+}
+
+fn serial(nb_agents: usize, plan: &[Step]) -> Vec<JointAction> {
+    plan.iter().map(|&(agent, action)| JointAction::single(nb_agents, agent, action)).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use defs::dir::{EAST, WEST};
+    use level::level::Level;
+
+    #[test]
+    fn independent_agents() {
+        let level = Level::new("red: 0, A\nblue: 1, B\n++++++++\n+0A  a +\n+1B  b +\n++++++++\n");
+        let start = State::new(&level);
+        let push = Action::Push(EAST, EAST);
+        let plan = vec!((0, push), (0, push), (0, push), (1, push), (1, push), (1, push));
+
+        let joint = parallelize(&start, &plan);
+        assert_eq!(joint.len(), 3);
+        assert!(joint.iter().all(|j| j[0] == push && j[1] == push));
+
+        let mut state = start.clone();
+        for step in &joint { step.execute(&mut state); }
+        assert!(state.is_goal_state(&level));
+    }
+
+    #[test]
+    fn shared_cells() {
+        // agent 0 walks where agent 1 just left, so it waits one step for the cell
+        let level = Level::new("red: 0\nblue: 1\n++++++\n+0 1 +\n++++++\n");
+        let start = State::new(&level);
+        let plan = vec!((1, Action::Move(EAST)), (0, Action::Move(EAST)), (0, Action::Move(EAST)), (0, Action::NoOp));
+
+        let joint = parallelize(&start, &plan);
+        assert_eq!(joint.len(), 2);
+        assert_eq!(joint[0].actions(), &[Action::Move(EAST), Action::Move(EAST)]);
+        assert_eq!(joint[1].actions(), &[Action::Move(EAST), Action::NoOp]);
+
+        // a plan that does not run is left as it is
+        let plan = vec!((0, Action::Move(WEST)));
+        assert_eq!(parallelize(&start, &plan).len(), 1);
+    }
+}
@@ -0,0 +1,166 @@
+use std::fmt;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+/// Why a search stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Termination {
+    Solved,
+    Exhausted,              // the whole space was searched without finding a goal
+    Incomplete,             // only part of the space could be searched, without finding a goal
+    ExpansionLimit,
+    Timeout,
+    MemoryLimit,
+    Cancelled               // stopped from another thread
+}
+
+/// Progress callback, given the statistics of the running search.
+pub type Report = fn(&SearchStats);
+
+/// Resource limits of a search run, all optional.
+#[derive(Clone, Default)]
+pub struct Limits {
+    pub max_expanded: Option<u64>,
+    pub deadline: Option<Instant>,
+    pub max_memory: Option<usize>,                          // approximate, in bytes
+    pub max_cost: Option<u32>,                              // prune nodes with g + h >= max_cost
+    pub progress: Option<(Duration, Report)>,               // periodic report
+    pub cancel: Option<Arc<AtomicBool>>                     // stop once set
+}
+
+#[derive(Debug, Clone)]
+pub struct SearchStats {
+    pub expanded: u64,
+    pub generated: u64,
+    pub frontier_peak: usize,
+    pub closed: usize,
+    pub memory: usize,      // approximate, in bytes
+    pub elapsed: Duration,
+    pub termination: Option<Termination>    // None while the search is running
+}
+
+/// Result of a search run.
+#[derive(Debug, Clone)]
+pub struct Outcome<S> {
+    pub solution: Option<S>,
+    pub stats: SearchStats
+}
+
+impl Limits {
+    pub fn new() -> Limits { Default::default() }
+
+    pub fn max_expanded(mut self, n: u64) -> Limits { self.max_expanded = Some(n); self }
+    pub fn deadline(mut self, t: Instant) -> Limits { self.deadline = Some(t); self }
+    pub fn timeout(self, d: Duration) -> Limits { self.deadline(Instant::now() + d) }
+    pub fn max_memory(mut self, bytes: usize) -> Limits { self.max_memory = Some(bytes); self }
+    pub fn max_cost(mut self, cost: u32) -> Limits { self.max_cost = Some(cost); self }
+
+    pub fn cancel(mut self, flag: Arc<AtomicBool>) -> Limits { self.cancel = Some(flag); self }
+
+    pub fn progress(mut self, every: Duration, report: Report) -> Limits {
+        self.progress = Some((every, report));
+        self
+    }
+}
+
+impl SearchStats {
+    pub fn nodes_per_sec(&self) -> f64 {
+        let secs = self.elapsed.as_secs() as f64 + self.elapsed.subsec_nanos() as f64 * 1e-9;
+        if secs > 0.0 { self.expanded as f64 / secs } else { 0.0 }
+    }
+}
+
+impl fmt::Display for SearchStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let elapsed = self.elapsed.as_secs() as f64 + self.elapsed.subsec_millis() as f64 * 1e-3;
+        write!(f, "expanded={} generated={} frontier_peak={} closed={} mem={}KiB time={:.3}s ({:.0} nodes/s)",
+            self.expanded, self.generated, self.frontier_peak, self.closed, self.memory / 1024,
+            elapsed, self.nodes_per_sec())?;
+
+        if let Some(t) = self.termination { write!(f, " {:?}", t)?; }
+        Ok(())
+    }
+}
+
+/// Keeps the statistics of a running search and enforces its limits.
+pub struct Monitor {
+    limits: Limits,
+    start: Instant,
+    last_report: Instant,
+    stats: SearchStats
+}
+
+impl Monitor {
+    pub fn new(limits: &Limits) -> Monitor {
+        let now = Instant::now();
+        Monitor {
+            limits: limits.clone(),
+            start: now,
+            last_report: now,
+            stats: SearchStats {
+                expanded: 0, generated: 0, frontier_peak: 0, closed: 0, memory: 0,
+                elapsed: Duration::from_secs(0), termination: None
+            }
+        }
+    }
+
+    pub fn stats(&self) -> &SearchStats { &self.stats }
+
+    pub fn generated(&mut self, n: usize) { self.stats.generated += n as u64; }
+
+    /// Accounts for one expansion, unless a limit was hit: then returns it and the expansion
+    /// must not happen, so `expanded` never goes past `max_expanded`.
+    pub fn expand(&mut self, frontier: usize, closed: usize, memory: usize) -> Option<Termination> {
+        let now = Instant::now();
+        let s = &mut self.stats;
+        s.elapsed = now - self.start;
+        s.frontier_peak = s.frontier_peak.max(frontier);
+        s.closed = closed;
+        s.memory = memory;
+
+        if self.limits.max_expanded.is_some_and(|n| s.expanded >= n) { return Some(Termination::ExpansionLimit); }
+        if self.limits.max_memory.is_some_and(|m| memory > m) { return Some(Termination::MemoryLimit); }
+        if self.limits.deadline.is_some_and(|d| now >= d) { return Some(Termination::Timeout); }
+        if self.limits.cancel.as_ref().is_some_and(|c| c.load(Ordering::Relaxed)) { return Some(Termination::Cancelled); }
+        s.expanded += 1;
+
+        if let Some((every, report)) = self.limits.progress {
+            if now - self.last_report >= every {
+                self.last_report = now;
+                report(s);
+            }
+        }
+        None
+    }
+
+    pub fn finish<S>(mut self, termination: Termination, solution: Option<S>) -> Outcome<S> {
+        self.stats.elapsed = self.start.elapsed();
+        self.stats.termination = Some(termination);
+        Outcome { solution: solution, stats: self.stats }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn limits() {
+        let mut m = Monitor::new(&Limits::new().max_expanded(10));
+        for _ in 0..10 { assert_eq!(m.expand(1, 1, 0), None); }
+        assert_eq!(m.expand(1, 1, 0), Some(Termination::ExpansionLimit));
+
+        let outcome = m.finish::<()>(Termination::ExpansionLimit, None);
+        assert_eq!(outcome.stats.expanded, 10);
+
+        let mut m = Monitor::new(&Limits::new().timeout(Duration::from_secs(0)));
+        assert_eq!(m.expand(0, 0, 0), Some(Termination::Timeout));
+
+        let flag = Arc::new(AtomicBool::new(false));
+        let mut m = Monitor::new(&Limits::new().cancel(flag.clone()));
+        assert_eq!(m.expand(0, 0, 0), None);
+        flag.store(true, Ordering::Relaxed);
+        assert_eq!(m.expand(0, 0, 0), Some(Termination::Cancelled));
+    }
+}
@@ -0,0 +1,67 @@
+use state::state::State;
+use super::anytime::{anytime, WEIGHTS};
+use super::backward::{bidirectional, reverse};
+use super::graph::{self, Solution};
+use super::heuristic::Heuristic;
+use super::idastar::idastar;
+use super::matching::Matching;
+use super::problem::{LevelProblem, Step};
+use super::stats::{Limits, Outcome};
+
+const IDA_TABLE_SIZE: usize = 1 << 20;
+
+/// The search configurations available to the solver.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Strategy {
+    Bfs,
+    Dfs,
+    AStar,
+    WeightedAStar(f64),
+    Greedy,
+    IdaStar,
+    Anytime,
+    Reverse,
+    Bidirectional
+}
+
+impl Strategy {
+    /// Parses `bfs`, `dfs`, `astar`, `wastar:<weight>`, `greedy`, `idastar`, `anytime`,
+    /// `reverse` or `bidir`.
+    pub fn parse(name: &str) -> Option<Strategy> {
+        match name {
+            "bfs"     => Some(Strategy::Bfs),
+            "dfs"     => Some(Strategy::Dfs),
+            "astar"   => Some(Strategy::AStar),
+            "greedy"  => Some(Strategy::Greedy),
+            "idastar" => Some(Strategy::IdaStar),
+            "anytime" => Some(Strategy::Anytime),
+            "reverse" => Some(Strategy::Reverse),
+            "bidir"   => Some(Strategy::Bidirectional),
+            _ if name.starts_with("wastar:") => {
+                name["wastar:".len()..].parse::<f64>().ok().map(Strategy::WeightedAStar)
+            }
+            _ => None
+        }
+    }
+
+    pub fn run(&self, problem: &LevelProblem, limits: &Limits) -> Outcome<Solution<Step>> {
+        let h = Matching::new(problem.level());
+        self.run_with(problem, &h, limits)
+    }
+
+    pub fn run_with<H>(&self, problem: &LevelProblem, h: &H, limits: &Limits) -> Outcome<Solution<Step>>
+        where H: Heuristic<State>
+    {
+        match *self {
+            Strategy::Bfs              => graph::bfs(problem, limits),
+            Strategy::Dfs              => graph::dfs(problem, limits),
+            Strategy::AStar            => graph::astar(problem, h, limits),
+            Strategy::WeightedAStar(w) => graph::weighted_astar(problem, h, w, limits),
+            Strategy::Greedy           => graph::greedy(problem, h, limits),
+            Strategy::IdaStar          => idastar(problem, h, IDA_TABLE_SIZE, limits),
+            Strategy::Anytime          => anytime(problem, h, &WEIGHTS, limits, |_, _| ()),
+            Strategy::Reverse          => reverse(problem, limits),
+            Strategy::Bidirectional    => bidirectional(problem, limits)
+        }
+    }
+}
@@ -0,0 +1,74 @@
+//! Local stand-in for the competition server.
+//!
+//! `server <level> <client> [args...]` starts the client, sends it the level followed by an
+//! empty line, then answers each joint action it prints with the success of every agent. Lines
+//! starting with `#` are comments, shown on stderr and not answered. Other lines that are not a
+//! joint action for every agent are answered with an error and take no time step. Once the client closes its
+//! output, the result is printed and the exit code is 0 if the level is solved.
+//!
+//! Without a client command, the joint actions are read from stdin and answered on stdout.
+
+extern crate aiclient;
+
+use std::env;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::process::{self, Command, Stdio};
+
+use aiclient::level::level::Level;
+use aiclient::state::simulator::Simulator;
+
+fn main() {
+    let args = env::args().skip(1).collect::<Vec<String>>();
+    if args.is_empty() {
+        eprintln!("Usage: server <level> [client command...]");
+        process::exit(2);
+    }
+
+    let mut text = String::new();
+    if File::open(&args[0]).and_then(|mut f| f.read_to_string(&mut text)).is_err() {
+        eprintln!("Could not read level.");
+        process::exit(2);
+    }
+    let level = Level::new(&text);
+    let mut server = Simulator::new(&level);
+
+    if args.len() == 1 {
+        let stdin = io::stdin();
+        let stdout = io::stdout();
+        serve(&mut server, stdin.lock(), stdout.lock());
+    } else {
+        let mut client = match Command::new(&args[1]).args(&args[2..]).stdin(Stdio::piped()).stdout(Stdio::piped()).spawn() {
+            Ok(client) => client,
+            Err(_) => {
+                eprintln!("Could not start client.");
+                process::exit(2);
+            }
+        };
+
+        let mut input = client.stdin.take().unwrap();
+        let output = BufReader::new(client.stdout.take().unwrap());
+        if write!(input, "{}\n\n", text.trim_end()).and_then(|_| input.flush()).is_ok() {
+            serve(&mut server, output, input);
+        }
+        let _ = client.wait();
+    }
+
+    println!("{}", server.report());
+    process::exit(if server.is_solved() { 0 } else { 1 });
+}
+
+// answers the joint actions read from `client` until it is done
+fn serve<R: BufRead, W: Write>(server: &mut Simulator, client: R, mut replies: W) {
+    for line in client.lines() {
+        let line = match line { Ok(line) => line, Err(_) => break };
+        if line.starts_with('#') {
+            eprintln!("{}", line);
+            continue;
+        }
+        if line.trim().is_empty() { continue; }
+
+        let reply = server.step(&line).unwrap_or_else(|e| format!("Error: {}", e));
+        if writeln!(replies, "{}", reply).and_then(|_| replies.flush()).is_err() { break; }
+    }
+}
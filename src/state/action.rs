@@ -0,0 +1,107 @@
+use std::fmt;
+
+use defs::dir::{self, Dir, DIRS};
+use defs::pos::Pos;
+
+/// A single agent action, as understood by the server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    NoOp,
+    Move(Dir),
+    Push(Dir, Dir),     // direction of the agent, direction of the box
+    Pull(Dir, Dir)      // direction of the agent, current direction of the box seen from the agent
+}
+
+/// Cells an action reads from and writes to, given the agent's position.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Effect {
+    pub agent_from: Pos,
+    pub agent_to: Pos,
+    pub box_move: Option<(Pos, Pos)>
+}
+
+impl Action {
+    /// Every action an agent can attempt, NoOp first.
+    pub fn all() -> Vec<Action> {
+        let mut actions = vec!(Action::NoOp);
+
+        for &d in &DIRS { actions.push(Action::Move(d)); }
+        for &a in &DIRS {
+            for &b in &DIRS {
+                if b != -a { actions.push(Action::Push(a, b)); }
+                if b != a  { actions.push(Action::Pull(a, b)); }
+            }
+        }
+
+        actions
+    }
+
+    /// The action undoing this one, performed from the agent's new position: a push becomes a
+    /// pull and a pull a push, the box going back where it was.
+    pub fn inverse(self) -> Action {
+        match self {
+            Action::NoOp       => Action::NoOp,
+            Action::Move(d)    => Action::Move(-d),
+            Action::Push(a, b) => Action::Pull(-a, b),
+            Action::Pull(a, b) => Action::Push(-a, b)
+        }
+    }
+
+    /// Action written as in the server protocol, e.g. `Push(E,S)`.
+    pub fn parse(text: &str) -> Option<Action> {
+        let text = text.trim();
+        if text == "NoOp" { return Some(Action::NoOp); }
+
+        let open = text.find('(')?;
+        if !text.ends_with(')') { return None; }
+        let dirs = text[open + 1..text.len() - 1].split(',')
+            .map(|d| dir::from_letter(d.trim())).collect::<Option<Vec<Dir>>>()?;
+
+        match (&text[..open], dirs.len()) {
+            ("Move", 1) => Some(Action::Move(dirs[0])),
+            ("Push", 2) => Some(Action::Push(dirs[0], dirs[1])),
+            ("Pull", 2) => Some(Action::Pull(dirs[0], dirs[1])),
+            _ => None
+        }
+    }
+
+    pub fn effect(self, agent: Pos) -> Effect {
+        match self {
+            Action::NoOp => Effect { agent_from: agent, agent_to: agent, box_move: None },
+            Action::Move(d) => Effect { agent_from: agent, agent_to: agent + d, box_move: None },
+            Action::Push(a, b) => Effect {
+                agent_from: agent,
+                agent_to: agent + a,
+                box_move: Some((agent + a, agent + a + b))
+            },
+            Action::Pull(a, b) => Effect {
+                agent_from: agent,
+                agent_to: agent + a,
+                box_move: Some((agent + b, agent))
+            }
+        }
+    }
+}
+
+impl Effect {
+    /// Cells that become occupied by this action and were not occupied by the acting objects.
+    pub fn destinations(&self) -> Vec<Pos> {
+        let mut cells = Vec::with_capacity(2);
+
+        if self.agent_to != self.agent_from { cells.push(self.agent_to); }
+        if let Some((_, to)) = self.box_move { cells.push(to); }
+
+        cells
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Action::NoOp       => write!(f, "NoOp"),
+            Action::Move(d)    => write!(f, "Move({})", dir::letter(d)),
+            Action::Push(a, b) => write!(f, "Push({},{})", dir::letter(a), dir::letter(b)),
+            Action::Pull(a, b) => write!(f, "Pull({},{})", dir::letter(a), dir::letter(b))
+        }
+    }
+}
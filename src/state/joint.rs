@@ -0,0 +1,218 @@
+use std::fmt;
+use std::ops::Index;
+
+use super::action::{Action, Effect};
+use super::state::State;
+
+/// One action per agent, executed simultaneously.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct JointAction {
+    actions: Vec<Action>
+}
+
+impl JointAction {
+    pub fn new(actions: Vec<Action>) -> JointAction {
+        JointAction { actions: actions }
+    }
+
+    pub fn noop(nb_agents: usize) -> JointAction {
+        JointAction { actions: vec!(Action::NoOp; nb_agents) }
+    }
+
+    /// `action` for `agent`, NoOp for everybody else.
+    pub fn single(nb_agents: usize, agent: usize, action: Action) -> JointAction {
+        let mut joint = Self::noop(nb_agents);
+        joint.actions[agent] = action;
+        joint
+    }
+
+    /// Joint action written as in the server protocol, e.g. `[Move(N),NoOp]`.
+    pub fn parse(text: &str) -> Option<JointAction> {
+        let text = text.trim();
+        if !text.starts_with('[') || !text.ends_with(']') { return None; }
+
+        // split on the commas between actions, not those between directions
+        let inner = &text[1..text.len() - 1];
+        let mut actions = Vec::new();
+        let (mut depth, mut begin) = (0, 0);
+        for (i, c) in inner.char_indices() {
+            match c {
+                '(' => depth += 1,
+                ')' => depth -= 1,
+                ',' if depth == 0 => {
+                    actions.push(Action::parse(&inner[begin..i])?);
+                    begin = i + 1;
+                }
+                _ => {}
+            }
+        }
+        actions.push(Action::parse(&inner[begin..])?);
+
+        Some(JointAction::new(actions))
+    }
+
+    pub fn len(&self) -> usize { self.actions.len() }
+    pub fn is_empty(&self) -> bool { self.actions.is_empty() }
+    pub fn actions(&self) -> &[Action] { &self.actions }
+
+    pub fn is_noop(&self) -> bool {
+        self.actions.iter().all(|&a| a == Action::NoOp)
+    }
+
+    /// Per-agent success of the joint action in `state`, following the server's rules:
+    ///  - each action must be applicable in the state *before* the step, so an agent cannot
+    ///    enter a cell that another agent or box is vacating during the same step;
+    ///  - two actions whose agents or boxes end up in the same cell both fail;
+    ///  - two actions moving the same box both fail.
+    pub fn outcome(&self, state: &State) -> Vec<bool> {
+        let mut success = self.applicable(state);
+
+        for (i, j) in self.conflicts(state) {
+            success[i] = false;
+            success[j] = false;
+        }
+
+        success
+    }
+
+    /// Pairs `(i, j)`, `i < j`, of actions applicable in `state` that clash: their agents or
+    /// boxes end up in the same cell, or they move the same box. Every agent of a pair fails,
+    /// so three agents entering one cell all fail.
+    pub fn conflicts(&self, state: &State) -> Vec<(usize, usize)> {
+        let applicable = self.applicable(state);
+        let effects = self.effects(state);
+        let mut pairs = Vec::new();
+
+        for i in 0..self.len() {
+            for j in (i+1)..self.len() {
+                if applicable[i] && applicable[j] && conflicting(&effects[i], &effects[j]) { pairs.push((i, j)); }
+            }
+        }
+
+        pairs
+    }
+
+    fn applicable(&self, state: &State) -> Vec<bool> {
+        assert_eq!(self.len(), state.nb_agents(), "joint action size mismatch");
+        (0..self.len()).map(|i| state.applicable(i, self.actions[i])).collect()
+    }
+
+    /// Executes the joint action on `state`; failed actions behave as NoOp.
+    pub fn execute(&self, state: &mut State) -> Vec<bool> {
+        let success = self.outcome(state);
+        let effects = self.effects(state);
+
+        for (agent, effect) in effects.iter().enumerate() {
+            if success[agent] { state.perform(agent, effect); }
+        }
+
+        success
+    }
+
+    fn effects(&self, state: &State) -> Vec<Effect> {
+        self.actions.iter().enumerate()
+            .map(|(agent, a)| a.effect(state.agent(agent)))
+            .collect()
+    }
+}
+
+fn conflicting(e: &Effect, f: &Effect) -> bool {
+    let same_box = match (e.box_move, f.box_move) {
+        (Some((b, _)), Some((c, _))) => b == c,
+        _ => false
+    };
+
+    let fd = f.destinations();
+    same_box || e.destinations().iter().any(|p| fd.contains(p))
+}
+
+impl Index<usize> for JointAction {
+    type Output = Action;
+    fn index(&self, agent: usize) -> &Action {
+        &self.actions[agent]
+    }
+}
+
+impl fmt::Display for JointAction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[")?;
+        for (i, a) in self.actions.iter().enumerate() {
+            if i > 0 { write!(f, ",")?; }
+            write!(f, "{}", a)?;
+        }
+        write!(f, "]")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use defs::dir::{NORTH, EAST, SOUTH, WEST};
+    use defs::pos::Pos;
+    use level::level::Level;
+
+    fn state(s: &str) -> State { State::new(&Level::new(s)) }
+
+    #[test]
+    fn same_destination() {
+        let mut s = state("+++++\n+0 1+\n+++++\n");
+        let joint = JointAction::new(vec!(Action::Move(EAST), Action::Move(WEST)));
+        assert_eq!(joint.execute(&mut s), vec!(false, false));
+        assert_eq!(s.agent(0), Pos::new(1, 1));
+    }
+
+    #[test]
+    fn three_into_one_cell() {
+        let mut s = state("+++++\n+0 1+\n++2++\n+++++\n");
+        let joint = JointAction::new(vec!(Action::Move(EAST), Action::Move(WEST), Action::Move(NORTH)));
+        assert_eq!(joint.conflicts(&s), vec!((0, 1), (0, 2), (1, 2)));
+        assert_eq!(joint.execute(&mut s), vec!(false, false, false));
+        assert_eq!(s.agent(2), Pos::new(2, 2));
+    }
+
+    #[test]
+    fn following_into_vacated_cell() {
+        let mut s = state("+++++\n+01 +\n+++++\n");
+        let joint = JointAction::new(vec!(Action::Move(EAST), Action::Move(EAST)));
+        assert_eq!(joint.execute(&mut s), vec!(false, true));
+        assert_eq!(s.agent(1), Pos::new(1, 3));
+    }
+
+    #[test]
+    fn same_box() {
+        let mut s = state("+++++\n+   +\n+0A1+\n+++++\n");
+        let joint = JointAction::new(vec!(Action::Push(EAST, NORTH), Action::Pull(NORTH, WEST)));
+        assert!(s.applicable(0, joint[0]) && s.applicable(1, joint[1]));
+        assert_eq!(joint.execute(&mut s), vec!(false, false));
+        assert!(s[Pos::new(2, 2)].is_box());
+    }
+
+    #[test]
+    fn box_and_agent_destination() {
+        let mut s = state("+++++\n+0A +\n++ 1+\n+++++\n");
+        let joint = JointAction::new(vec!(Action::Push(EAST, EAST), Action::Move(NORTH)));
+        assert_eq!(joint.execute(&mut s), vec!(false, false));
+    }
+
+    #[test]
+    fn independent_actions() {
+        let mut s = state("++++++\n+0A  +\n+   1+\n++++++\n");
+        let joint = JointAction::new(vec!(Action::Push(EAST, EAST), Action::Move(SOUTH)));
+        assert_eq!(joint.execute(&mut s), vec!(true, false));
+
+        let joint = JointAction::new(vec!(Action::Pull(WEST, EAST), Action::Move(WEST)));
+        assert_eq!(joint.execute(&mut s), vec!(true, true));
+        assert!(s[Pos::new(1, 2)].is_box());
+        assert_eq!(s.agent(0), Pos::new(1, 1));
+        assert_eq!(format!("{}", joint), "[Pull(W,E),Move(W)]");
+    }
+
+    #[test]
+    fn parse() {
+        let joint = JointAction::new(vec!(Action::Pull(WEST, EAST), Action::NoOp, Action::Move(NORTH)));
+        assert_eq!(JointAction::parse(&format!("{}", joint)), Some(joint));
+        assert_eq!(JointAction::parse("[Push(E, S), NoOp]"), Some(JointAction::new(vec!(Action::Push(EAST, SOUTH), Action::NoOp))));
+        assert_eq!(JointAction::parse("[Move(E,S)]"), None);
+        assert_eq!(JointAction::parse("Move(E)"), None);
+    }
+}
@@ -0,0 +1,7 @@
+pub mod action;
+#[allow(clippy::module_inception)]
+pub mod state;
+pub mod joint;
+pub mod zobrist;
+pub mod packed;
+pub mod simulator;
@@ -0,0 +1,157 @@
+use std::hash::{Hash, Hasher};
+use std::mem::size_of;
+
+use defs::pos::{Pos, NULL_POS};
+use level::component::Component;
+use level::item::Item;
+use level::level::Level;
+use super::state::State;
+use super::zobrist::Zobrist;
+
+/// Compact encoding of a `State` for closed sets: the cell index of each agent and one bitset
+/// of box positions per (letter, color) pair.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackedState {
+    hash: u64,
+    agents: Box<[i16]>,     // cell index of each agent, -1 if absent
+    boxes: Box<[u64]>       // `words` words per box kind, in the order of `Encoder::kinds`
+}
+
+/// Packs and unpacks the states of one level.
+pub struct Encoder {
+    comp: Component,        // indexes every free cell of the level
+    kinds: Vec<Item>,       // distinct boxes of the level
+    agents: Vec<Item>,      // agent item of each id
+    words: usize,
+    zobrist: Zobrist,
+    empty: State            // the level without agents and boxes
+}
+
+impl Encoder {
+    pub fn new(level: &Level) -> Encoder {
+        let comp = Component::new(level.grid());
+        let mut empty = State::new(level);
+        let mut kinds = Vec::new();
+        let agents = empty.agents().iter()
+            .map(|&pos| if pos == NULL_POS { Item::empty() } else { empty[pos] })
+            .collect::<Vec<Item>>();
+        let (rows, cols) = level.size();
+
+        for row in 0..rows {
+            for col in 0..cols {
+                let pos = Pos::new(row as i8, col as i8);
+                let item = empty[pos];
+
+                if item.is_box() && !kinds.contains(&item) { kinds.push(item); }
+                if item.is_agent() || item.is_box() { empty.remove(pos); }
+            }
+        }
+
+        let nb_cells = comp.nb_free_cells();
+
+        Encoder {
+            zobrist: Zobrist::new(nb_cells, agents.len() + kinds.len()),
+            words: nb_cells.div_ceil(64),
+            comp: comp,
+            kinds: kinds,
+            agents: agents,
+            empty: empty
+        }
+    }
+
+    pub fn kinds(&self) -> &[Item] { &self.kinds }
+
+    fn box_kind(&self, item: Item) -> usize {
+        self.kinds.iter().position(|&k| k == item).expect("unknown box")
+    }
+
+    pub fn pack(&self, state: &State) -> PackedState {
+        let mut hash = 0;
+        let mut agents = vec!(-1i16; self.agents.len());
+        let mut boxes = vec!(0u64; self.words * self.kinds.len());
+
+        for (id, &pos) in state.agents().iter().enumerate() {
+            if pos == NULL_POS { continue; }
+            let index = self.comp.index_of(pos);
+            agents[id] = index;
+            hash ^= self.zobrist.key(id, index);
+        }
+
+        for index in 0..self.comp.nb_free_cells() {
+            let item = state[self.comp.pos_of(index as i16)];
+            if !item.is_box() { continue; }
+
+            let kind = self.box_kind(item);
+            boxes[kind * self.words + index / 64] |= 1 << (index % 64);
+            hash ^= self.zobrist.key(self.agents.len() + kind, index as i16);
+        }
+
+        PackedState {
+            hash: hash,
+            agents: agents.into_boxed_slice(),
+            boxes: boxes.into_boxed_slice()
+        }
+    }
+
+    pub fn unpack(&self, packed: &PackedState) -> State {
+        let mut state = self.empty.clone();
+
+        for (id, &index) in packed.agents.iter().enumerate() {
+            if index < 0 { continue; }
+            let pos = self.comp.pos_of(index);
+            state.place(pos, self.agents[id]);
+        }
+
+        for (kind, &item) in self.kinds.iter().enumerate() {
+            let bits = &packed.boxes[kind * self.words..(kind + 1) * self.words];
+            for (w, &word) in bits.iter().enumerate() {
+                let mut word = word;
+                while word != 0 {
+                    let index = w * 64 + word.trailing_zeros() as usize;
+                    state.place(self.comp.pos_of(index as i16), item);
+                    word &= word - 1;
+                }
+            }
+        }
+
+        state
+    }
+}
+
+impl PackedState {
+    pub fn hash(&self) -> u64 { self.hash }
+
+    /// Approximate number of bytes used by the encoding.
+    pub fn mem_size(&self) -> usize {
+        size_of::<PackedState>() + self.agents.len() * size_of::<i16>() + self.boxes.len() * size_of::<u64>()
+    }
+}
+
+impl Hash for PackedState {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.hash.hash(state);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use defs::dir::WEST;
+    use state::action::Action;
+
+    #[test]
+    fn pack_unpack() {
+        let level = Level::from_file("levels/rbts.lvl").unwrap();
+        let encoder = Encoder::new(&level);
+        let start = State::new(&level);
+        let moved = start.apply(1, Action::Move(WEST)).unwrap();
+
+        let (p, q) = (encoder.pack(&start), encoder.pack(&moved));
+        assert!(p != q);
+        assert!(p.hash() != q.hash());
+        assert_eq!(encoder.unpack(&p), start);
+        assert_eq!(encoder.unpack(&q), moved);
+        assert_eq!(encoder.pack(&encoder.unpack(&q)), q);
+        assert!(p.mem_size() * 3 < size_of::<Item>() * start.size().0 * start.size().1);
+    }
+}
@@ -0,0 +1,70 @@
+use level::level::Level;
+use super::joint::JointAction;
+use super::state::State;
+
+/// Plays the server's part: runs the joint actions a client sends, with the executor's rules,
+/// and tells which actions succeeded.
+pub struct Simulator<'a> {
+    level: &'a Level,
+    state: State,
+    time: usize
+}
+
+impl<'a> Simulator<'a> {
+    pub fn new(level: &'a Level) -> Simulator<'a> {
+        Simulator { level: level, state: State::new(level), time: 0 }
+    }
+
+    /// Runs one line of the protocol, e.g. `[Move(N),NoOp]`, and returns the reply to send.
+    /// A line that is not a joint action for every agent of the level is an error, and does
+    /// not count as a time step.
+    pub fn step(&mut self, line: &str) -> Result<String, &'static str> {
+        let joint = match JointAction::parse(line) {
+            Some(joint) => joint,
+            None => return Err("not a joint action")
+        };
+        if joint.len() != self.state.nb_agents() { return Err("not one action per agent"); }
+
+        let success = joint.execute(&mut self.state);
+        self.time += 1;
+        Ok(reply(&success))
+    }
+
+    pub fn is_solved(&self) -> bool { self.state.is_goal_state(self.level) }
+
+    /// Final report, as the server prints it once the client is done.
+    pub fn report(&self) -> String {
+        if self.is_solved() {
+            format!("Level solved in {} joint actions.", self.time)
+        } else {
+            format!("Level not solved after {} joint actions.", self.time)
+        }
+    }
+}
+
+/// Per-agent success in the protocol syntax, e.g. `[true,false]`.
+pub fn reply(success: &[bool]) -> String {
+    let words = success.iter().map(|&ok| if ok { "true" } else { "false" }).collect::<Vec<&str>>();
+    format!("[{}]", words.join(","))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn protocol() {
+        let level = Level::new("red: 0, A\nblue: 1\n+++++++\n+0A  a+\n+1    +\n+++++++\n");
+        let mut server = Simulator::new(&level);
+
+        assert_eq!(server.step("[Push(E,E),Move(E)]"), Ok("[true,true]".to_string()));
+        assert_eq!(server.step("[Push(E,E),Move(N)]"), Ok("[true,false]".to_string()));
+        assert!(server.step("[Push(E,E)]").is_err());
+        assert!(server.step("hello").is_err());
+        assert!(!server.is_solved());
+
+        assert_eq!(server.step("[Push(E,E),NoOp]"), Ok("[true,true]".to_string()));
+        assert!(server.is_solved());
+        assert_eq!(server.report(), "Level solved in 3 joint actions.");
+    }
+}
@@ -0,0 +1,284 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::mem::size_of;
+use std::ops::Index;
+
+use defs::grid::Grid;
+use defs::pos::{Pos, NULL_POS};
+use level::goal::Goal;
+use level::item::{Item, Color};
+use level::level::Level;
+use super::action::{Action, Effect};
+use super::zobrist;
+
+/// The dynamic part of a level: walls, agents and boxes. Goals stay in the `Level`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct State {
+    cells: Grid<Item>,
+    agents: Vec<Pos>,       // agent positions indexed by agent id, NULL_POS for missing ids
+    hash: u64               // Zobrist hash of the agents and boxes, kept up to date by `perform`
+}
+
+/// What is needed to revert an action applied with `State::apply_in_place`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Undo {
+    agent: usize,
+    effect: Effect
+}
+
+impl State {
+    pub fn new(level: &Level) -> State {
+        let (rows, cols) = level.size();
+        let mut cells = Grid::<Item>::new(rows, cols);
+        let mut agents = Vec::new();
+        let mut hash = 0;
+
+        for row in 0..rows {
+            for col in 0..cols {
+                let pos = Pos::new(row as i8, col as i8);
+                let item = level[pos];
+
+                if item.is_goal() { continue; }
+                cells[pos] = item;
+
+                if item.is_agent() || item.is_box() { hash ^= zobrist::key(pos, item); }
+                if item.is_agent() {
+                    let id = item.id() as usize;
+                    if agents.len() <= id { agents.resize(id + 1, NULL_POS); }
+                    agents[id] = pos;
+                }
+            }
+        }
+
+        State { cells: cells, agents: agents, hash: hash }
+    }
+
+    pub fn size(&self) -> (usize, usize) { self.cells.size() }
+    pub fn hash(&self) -> u64 { self.hash }
+
+    /// Approximate number of bytes used by the state, heap included.
+    pub fn mem_size(&self) -> usize {
+        let (rows, cols) = self.size();
+        size_of::<State>() + rows * cols * size_of::<Item>() + self.agents.len() * size_of::<Pos>()
+    }
+
+    pub fn nb_agents(&self) -> usize { self.agents.len() }
+    pub fn agents(&self) -> &[Pos] { &self.agents }
+    pub fn agent(&self, id: usize) -> Pos { self.agents[id] }
+
+    pub fn in_bounds(&self, pos: Pos) -> bool {
+        let (rows, cols) = self.size();
+        pos.row >= 0 && pos.col >= 0 && (pos.row as usize) < rows && (pos.col as usize) < cols
+    }
+
+    pub fn is_free(&self, pos: Pos) -> bool {
+        self.in_bounds(pos) && self.cells[pos].is_empty()
+    }
+
+    pub fn satisfies(&self, goal: &Goal) -> bool {
+        goal.is_satisfied_by(self.cells[goal.pos])
+    }
+
+    pub fn is_goal_state(&self, level: &Level) -> bool {
+        level.goals().iter().all(|g| self.satisfies(g))
+    }
+
+    pub fn nb_satisfied(&self, level: &Level) -> usize {
+        level.goals().iter().filter(|g| self.satisfies(g)).count()
+    }
+
+    pub fn nb_unsatisfied(&self, level: &Level) -> usize {
+        level.goals().len() - self.nb_satisfied(level)
+    }
+
+    pub fn unsatisfied_by_color(&self, level: &Level) -> HashMap<Color, Vec<Goal>> {
+        let mut map = HashMap::<Color, Vec<Goal>>::new();
+        for goal in level.goals().iter().filter(|g| !self.satisfies(g)) {
+            map.entry(goal.color).or_default().push(*goal);
+        }
+        map
+    }
+
+    /// Whether `agent` may perform `action` on its own, ignoring the other agents' actions.
+    pub fn applicable(&self, agent: usize, action: Action) -> bool {
+        let pos = self.agents[agent];
+        if pos == NULL_POS { return action == Action::NoOp; }
+
+        match action {
+            Action::NoOp       => true,
+            Action::Move(d)    => self.is_free(pos + d),
+            Action::Push(a, b) => self.can_move_box(pos, pos + a) && self.is_free(pos + a + b),
+            Action::Pull(a, b) => self.can_move_box(pos, pos + b) && self.is_free(pos + a)
+        }
+    }
+
+    fn can_move_box(&self, agent: Pos, pos: Pos) -> bool {
+        self.in_bounds(pos) && self.cells[pos].is_box() && Item::compatible(&self.cells[agent], &self.cells[pos])
+    }
+
+    /// Single agent successor; None if the action is not applicable.
+    pub fn apply(&self, agent: usize, action: Action) -> Option<State> {
+        if !self.applicable(agent, action) { return None; }
+
+        let mut next = self.clone();
+        next.perform(agent, &action.effect(self.agents[agent]));
+        Some(next)
+    }
+
+    /// Applies `action` without cloning the state. The returned record reverts it with `undo`.
+    pub fn apply_in_place(&mut self, agent: usize, action: Action) -> Option<Undo> {
+        if !self.applicable(agent, action) { return None; }
+
+        let effect = action.effect(self.agents[agent]);
+        self.perform(agent, &effect);
+        Some(Undo { agent: agent, effect: effect })
+    }
+
+    /// Reverts the last action applied with `apply_in_place`. Undos must be replayed in
+    /// reverse order of application.
+    pub fn undo(&mut self, undo: Undo) {
+        let effect = Effect {
+            agent_from: undo.effect.agent_to,
+            agent_to: undo.effect.agent_from,
+            box_move: undo.effect.box_move.map(|(from, to)| (to, from))
+        };
+        self.perform(undo.agent, &effect);
+    }
+
+    /// Moves the objects described by `effect`, which must be applicable.
+    pub fn perform(&mut self, agent: usize, effect: &Effect) {
+        // lift every moving object before putting any down, so that a destination may be
+        // the source of the other object (Push and Pull)
+        let agent_item = self.take(effect.agent_from);
+        let box_item = effect.box_move.map(|(from, _)| self.take(from));
+
+        self.put(effect.agent_to, agent_item);
+        if let (Some((_, to)), Some(item)) = (effect.box_move, box_item) { self.put(to, item); }
+
+        self.agents[agent] = effect.agent_to;
+    }
+
+    /// Puts `item` on the free cell `pos`.
+    pub fn place(&mut self, pos: Pos, item: Item) {
+        assert!(self.is_free(pos), "cell is not free");
+        self.put(pos, item);
+
+        if item.is_agent() {
+            let id = item.id() as usize;
+            if self.agents.len() <= id { self.agents.resize(id + 1, NULL_POS); }
+            self.agents[id] = pos;
+        }
+    }
+
+    /// Removes the agent or box on `pos`; the agent keeps its id with a NULL_POS position.
+    pub fn remove(&mut self, pos: Pos) -> Item {
+        let item = self.take(pos);
+        if item.is_agent() { self.agents[item.id() as usize] = NULL_POS; }
+        item
+    }
+
+    fn take(&mut self, pos: Pos) -> Item {
+        let item = self.cells[pos];
+        self.hash ^= zobrist::key(pos, item);
+        self.cells[pos] = Item::empty();
+        item
+    }
+
+    fn put(&mut self, pos: Pos, item: Item) {
+        self.hash ^= zobrist::key(pos, item);
+        self.cells[pos] = item;
+    }
+}
+
+impl Hash for State {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.hash.hash(state);
+    }
+}
+
+impl Index<Pos> for State {
+    type Output = Item;
+    fn index(&self, pos: Pos) -> &Item {
+        &self.cells[pos]
+    }
+}
+
+impl fmt::Display for State {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (rows, cols) = self.size();
+        for row in 0..rows {
+            for col in 0..cols {
+                write!(f, "{}", self[Pos::new(row as i8, col as i8)])?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use defs::dir::{NORTH, EAST, SOUTH, WEST};
+
+    #[test]
+    fn apply_undo() {
+        let level = Level::new("+++++++\n+  A  +\n+ 0 B +\n+     +\n+++++++\n");
+        let start = State::new(&level);
+        let mut state = start.clone();
+        let mut undos = Vec::new();
+
+        let actions = [Action::Move(NORTH), Action::Push(EAST, SOUTH), Action::Pull(EAST, SOUTH),
+                       Action::Push(SOUTH, SOUTH), Action::Move(WEST), Action::Move(NORTH), Action::NoOp];
+
+        for &a in &actions {
+            let expected = state.apply(0, a);
+            let undo = state.apply_in_place(0, a);
+
+            assert_eq!(expected.is_some(), undo.is_some());
+            if let Some(u) = undo {
+                assert_eq!(Some(&state), expected.as_ref());
+                assert_eq!(state.hash(), full_hash(&state));
+                undos.push(u);
+            }
+        }
+
+        assert_eq!(undos.len(), 6);
+        assert!(state.hash() != start.hash());
+
+        while let Some(u) = undos.pop() { state.undo(u); }
+        assert_eq!(state, start);
+        assert_eq!(state.hash(), start.hash());
+    }
+
+    #[test]
+    fn goal_metrics() {
+        let level = Level::new("blue: A, 0\nred: B, 1\n+++++++\n+0A  a+\n+1Bb  +\n+++++++\n");
+        let mut state = State::new(&level);
+
+        assert_eq!(state.nb_unsatisfied(&level), 2);
+        assert_eq!(state.unsatisfied_by_color(&level).len(), 2);
+
+        state.apply_in_place(1, Action::Push(EAST, EAST)).unwrap();
+        assert_eq!(state.nb_satisfied(&level), 1);
+        assert!(!state.is_goal_state(&level));
+        assert_eq!(state.unsatisfied_by_color(&level)[&Color::Blue][0].pos, Pos::new(1, 5));
+
+        for _ in 0..3 { state.apply_in_place(0, Action::Push(EAST, EAST)).unwrap(); }
+        assert!(state.is_goal_state(&level));
+    }
+
+    fn full_hash(state: &State) -> u64 {
+        let (rows, cols) = state.size();
+        let mut hash = 0;
+        for row in 0..rows {
+            for col in 0..cols {
+                let pos = Pos::new(row as i8, col as i8);
+                let item = state[pos];
+                if item.is_agent() || item.is_box() { hash ^= zobrist::key(pos, item); }
+            }
+        }
+        hash
+    }
+}
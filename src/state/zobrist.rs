@@ -0,0 +1,43 @@
+use defs::pos::Pos;
+use level::item::Item;
+
+/// Zobrist key of `item` standing on `pos`.
+///
+/// Keys are derived with splitmix64 from the position and the item's kind, letter and color,
+/// so no table has to be built or passed around.
+pub fn key(pos: Pos, item: Item) -> u64 {
+    let kind = if item.is_agent() { 1u64 } else if item.is_box() { 2 } else { 0 };
+    let seed = (pos.row as u8 as u64)
+        | (pos.col as u8 as u64) << 8
+        | kind << 16
+        | (item.id() as u64) << 24
+        | (item.color() as u64) << 32;
+
+    splitmix64(seed)
+}
+
+pub fn splitmix64(seed: u64) -> u64 {
+    let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/// Zobrist table keyed on the cell index of a `Component` and an object kind, i.e. an agent
+/// id or a (letter, color) pair of box.
+#[derive(Debug, Clone)]
+pub struct Zobrist {
+    nb_cells: usize,
+    keys: Vec<u64>
+}
+
+impl Zobrist {
+    pub fn new(nb_cells: usize, nb_kinds: usize) -> Zobrist {
+        let keys = (0..nb_cells * nb_kinds).map(|i| splitmix64(i as u64)).collect();
+        Zobrist { nb_cells: nb_cells, keys: keys }
+    }
+
+    pub fn key(&self, kind: usize, index: i16) -> u64 {
+        self.keys[kind * self.nb_cells + index as usize]
+    }
+}